@@ -0,0 +1,71 @@
+// How far the adjust keys move a parameter.
+//
+// Steps are in the unit a parameter is shown in, so a press is half a decibel on a gain and ten
+// milliseconds on a time rather than the same fraction of every range. Holding a key down speeds
+// the normal and coarse steps up; fine steps stay put for precise settings.
+
+use crate::effect_params::Param;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepSize {
+    Fine,
+    Normal,
+    Coarse,
+}
+
+/// Presses closer together than this count as the key being held down.
+const REPEAT_WINDOW: Duration = Duration::from_millis(200);
+/// The step doubles after this many repeats, and again after as many more.
+const REPEATS_PER_DOUBLING: u32 = 10;
+/// The step grows to at most this many times its size.
+const MAX_MULTIPLIER: u32 = 8;
+
+/// One step of `size` for `param`, in the unit its value is shown in.
+pub fn step(param: Param, size: StepSize) -> f32 {
+    // Fine, normal and coarse.
+    let [fine, normal, coarse] = match param {
+        // Decibels.
+        Param::DistortionLevel => [0.1, 0.5, 3.0],
+        // Percent.
+        Param::DistortionDrive | Param::DelayDecay => [0.1, 1.0, 10.0],
+        // Milliseconds.
+        Param::DelayTime => [1.0, 10.0, 100.0],
+        // Switches and choices move one option at a time.
+        Param::DistortionBypass
+        | Param::DelayBypass
+        | Param::DistortionShape
+        | Param::DelayMode
+        | Param::DelayFilter => [1.0, 1.0, 1.0],
+    };
+    match size {
+        StepSize::Fine => fine,
+        StepSize::Normal => normal,
+        StepSize::Coarse => coarse,
+    }
+}
+
+/// Tracks repeated presses to speed up held keys.
+#[derive(Default)]
+pub struct Acceleration {
+    last: Option<(Param, bool, Instant)>,
+    repeats: u32,
+}
+
+impl Acceleration {
+    /// How many steps a press moving `param` up or down should take.
+    pub fn press(&mut self, param: Param, up: bool, size: StepSize) -> f32 {
+        let now = Instant::now();
+        let repeated = self.last.is_some_and(|(last_param, last_up, at)| {
+            last_param == param && last_up == up && now.duration_since(at) < REPEAT_WINDOW
+        });
+        self.repeats = if repeated { self.repeats + 1 } else { 0 };
+        self.last = Some((param, up, now));
+
+        if size == StepSize::Fine {
+            return 1.0;
+        }
+        let doublings = self.repeats / REPEATS_PER_DOUBLING;
+        (1_u32 << doublings.min(MAX_MULTIPLIER.ilog2())) as f32
+    }
+}
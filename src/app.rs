@@ -1,28 +1,73 @@
-use crate::EffectParams;
+use crate::adjust::{self, Acceleration, StepSize};
+use crate::config::Config;
+use crate::effect_params::{EffectParams, Param};
+use crate::engine_state::EngineState;
+use crate::history::{Change, History, SlotChange};
+use crate::keymap::{Action, Context, Keymap};
+use crate::mouse::{Click, Drag, HitAreas};
+use crate::palette::{Command, Palette};
+use crate::pipeline::PipelineCommand;
+use crate::settings::SettingsState;
+use crate::snapshots::Snapshots;
+use crate::theme::Theme;
 use crate::ui;
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+        MouseButton, MouseEvent, MouseEventKind,
+    },
+    execute,
     terminal::{disable_raw_mode, enable_raw_mode},
 };
-use ratatui::{
-    DefaultTerminal, Frame,
-    buffer::Buffer,
-    layout::Rect,
-    style::Stylize,
-    symbols::border,
-    text::{Line, Text},
-    widgets::{Block, Paragraph, Widget},
-};
+use ratatui::DefaultTerminal;
+use ratatui::layout::Position;
 use std::io;
 use std::sync::{
     Arc,
     atomic::{AtomicBool, Ordering},
+    mpsc::Sender,
 };
+use std::time::{Duration, Instant};
+
+/// How often to look for parameter changes made elsewhere between frames.
+const CHANGE_POLL: Duration = Duration::from_millis(10);
 
-pub fn init_ui(running: Arc<AtomicBool>, ui_params: Arc<EffectParams>) -> io::Result<()> {
+/// Index of the audio settings tab.
+pub const SETTINGS_TAB: usize = 3;
+/// Index of the log tab.
+pub const LOG_TAB: usize = 4;
+/// How many lines Page Up and Page Down scroll the log by.
+const LOG_PAGE: usize = 10;
+/// The knobs on each effect tab, left to right.
+pub const TAB_PARAMS: [&[Param]; 3] = [
+    &[
+        Param::DistortionLevel,
+        Param::DistortionDrive,
+        Param::DistortionShape,
+        Param::DistortionBypass,
+    ],
+    &[
+        Param::DelayTime,
+        Param::DelayDecay,
+        Param::DelayMode,
+        Param::DelayFilter,
+        Param::DelayBypass,
+    ],
+    &[],
+];
+
+pub fn init_ui(
+    running: Arc<AtomicBool>,
+    ui_params: Arc<EffectParams>,
+    engine: Arc<EngineState>,
+    commands: Sender<PipelineCommand>,
+    config: Config,
+) -> io::Result<()> {
     let mut terminal = ratatui::init();
-    let mut app = App::new(running, ui_params);
+    execute!(io::stdout(), EnableMouseCapture)?;
+    let mut app = App::new(running, ui_params, engine, commands, config);
     let app_result = app.run(&mut terminal);
+    execute!(io::stdout(), DisableMouseCapture)?;
     disable_raw_mode()?;
     ratatui::restore();
     app_result
@@ -32,54 +77,377 @@ pub struct App<'a> {
     running: Arc<AtomicBool>,
     pub tabs: TabsState<'a>,
     pub effect_params: Arc<EffectParams>,
+    pub engine: Arc<EngineState>,
+    commands: Sender<PipelineCommand>,
     pub param_selection: ParamSelection,
+    pub settings: SettingsState,
+    /// How many lines the log tab is scrolled back from the newest one.
+    pub log_scroll: usize,
+    history: History,
+    pub snapshots: Snapshots,
+    pub keymap: Keymap,
+    /// Where the tabs and knobs were drawn last.
+    pub hit_areas: HitAreas,
+    drag: Option<Drag>,
+    last_click: Option<Click>,
+    acceleration: Acceleration,
+    /// A value being typed in for the selected parameter.
+    pub entry: Option<ValueEntry>,
+    /// Time between redraws for live data.
+    frame: Duration,
+    pub theme: Theme,
+    /// Beats per minute that times typed in and shown as note lengths are timed against.
+    pub tempo: f32,
+    /// What is shown over the tabs, taking the keys while it is open.
+    pub overlay: Overlay,
+}
+
+#[derive(Default)]
+pub enum Overlay {
+    #[default]
+    None,
+    /// The keys of the current screen.
+    Help {
+        scroll: usize,
+    },
+    Palette(Palette),
+}
+
+#[derive(Default)]
+pub struct ValueEntry {
+    pub text: String,
+    /// Why the text couldn't be used, after trying to.
+    pub error: Option<String>,
 }
 
 impl<'a> App<'a> {
-    pub fn new(running: Arc<AtomicBool>, effect_params: Arc<EffectParams>) -> Self {
+    pub fn new(
+        running: Arc<AtomicBool>,
+        effect_params: Arc<EffectParams>,
+        engine: Arc<EngineState>,
+        commands: Sender<PipelineCommand>,
+        config: Config,
+    ) -> Self {
         App {
-            tabs: TabsState::new(vec!["Distorion", "Delay", "Reverb"]),
+            tabs: TabsState::new(vec!["Distorion", "Delay", "Reverb", "Settings", "Log"]),
             running,
             effect_params,
+            engine,
+            commands,
             param_selection: ParamSelection::new(),
+            settings: SettingsState::new(),
+            log_scroll: 0,
+            history: History::new(),
+            snapshots: Snapshots::new(),
+            keymap: config.keymap,
+            frame: Duration::from_secs_f32(1.0 / config.frame_rate as f32),
+            theme: config.theme,
+            tempo: config.tempo,
+            hit_areas: HitAreas::default(),
+            drag: None,
+            last_click: None,
+            acceleration: Acceleration::default(),
+            entry: None,
+            overlay: Overlay::None,
         }
     }
 
-    /// runs the application's main loop until the user quits
+    /// Runs the application's main loop until the user quits. The screen is redrawn once a
+    /// frame for the live stream data, and straight away after input or when a parameter
+    /// changes, whoever changed it.
     pub fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
         enable_raw_mode()?;
+        let mut next_frame = Instant::now();
+        let mut seen_changes = self.effect_params.changes();
+        let mut redraw = true;
         while self.running.load(Ordering::SeqCst) {
-            terminal.draw(|frame| ui::draw(frame, self))?;
-            self.handle_events()?;
+            let now = Instant::now();
+            if now >= next_frame {
+                redraw = true;
+                next_frame = now + self.frame;
+            }
+            let changes = self.effect_params.changes();
+            if changes != seen_changes {
+                seen_changes = changes;
+                redraw = true;
+            }
+            if redraw {
+                terminal.draw(|frame| ui::draw(frame, self))?;
+                redraw = false;
+            }
+
+            let timeout = next_frame
+                .saturating_duration_since(Instant::now())
+                .min(CHANGE_POLL);
+            if event::poll(timeout)? {
+                // Take everything that queued up before drawing again.
+                while event::poll(Duration::ZERO)? {
+                    self.handle_event(event::read()?);
+                }
+                redraw = true;
+            }
         }
         Ok(())
     }
 
-    /// updates the application's state based on user input
-    fn handle_events(&mut self) -> io::Result<()> {
-        match event::read()? {
+    /// Updates the application's state based on user input.
+    fn handle_event(&mut self, event: Event) {
+        match event {
             // it's important to check that the event is a key press event as
             // crossterm also emits key release and repeat events on Windows.
             Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
                 self.handle_key_event(key_event)
             }
+            Event::Mouse(mouse_event) => self.handle_mouse_event(mouse_event),
             _ => {}
-        };
-        Ok(())
+        }
     }
 
     fn handle_key_event(&mut self, key_event: KeyEvent) {
+        if self.entry.is_some() {
+            return self.handle_entry_key_event(key_event);
+        }
+        match self.overlay {
+            Overlay::None => {}
+            Overlay::Help { .. } => return self.handle_help_key_event(key_event),
+            Overlay::Palette(_) => return self.handle_palette_key_event(key_event),
+        }
+        if let Some(action) = self.keymap.action(self.context(), key_event) {
+            self.perform(action);
+        }
+    }
+
+    /// While typing in a value, keys edit the text instead of doing their usual thing.
+    fn handle_entry_key_event(&mut self, key_event: KeyEvent) {
+        let Some(entry) = &mut self.entry else {
+            return;
+        };
         match key_event.code {
-            KeyCode::Char('q') => self.exit(),
-            KeyCode::Left => self.previous_param(),
-            KeyCode::Right => self.next_param(),
-            KeyCode::Up => self.increase_param(),
-            KeyCode::Down => self.decrease_param(),
-            KeyCode::Tab => self.next_tab(),
+            KeyCode::Char(c) => {
+                entry.text.push(c);
+                entry.error = None;
+            }
+            KeyCode::Backspace => {
+                entry.text.pop();
+                entry.error = None;
+            }
+            KeyCode::Esc => self.entry = None,
+            KeyCode::Enter => self.commit_entry(),
             _ => {}
         }
     }
 
+    fn handle_help_key_event(&mut self, key_event: KeyEvent) {
+        let Overlay::Help { scroll } = &mut self.overlay else {
+            return;
+        };
+        // The help scrolls with the log's keys.
+        match self.keymap.action(Context::Log, key_event) {
+            Some(Action::ScrollUp) => *scroll = scroll.saturating_sub(1),
+            Some(Action::ScrollDown) => *scroll += 1,
+            Some(Action::PageUp) => *scroll = scroll.saturating_sub(LOG_PAGE),
+            Some(Action::PageDown) => *scroll += LOG_PAGE,
+            Some(Action::Close | Action::Help | Action::Quit) => self.overlay = Overlay::None,
+            _ => {}
+        }
+    }
+
+    /// While the palette is open, keys edit the search and pick a match.
+    fn handle_palette_key_event(&mut self, key_event: KeyEvent) {
+        let context = self.context();
+        let Overlay::Palette(palette) = &mut self.overlay else {
+            return;
+        };
+        match key_event.code {
+            KeyCode::Char(c) => {
+                palette.query.push(c);
+                palette.selected = 0;
+                palette.error = None;
+            }
+            KeyCode::Backspace => {
+                palette.query.pop();
+                palette.selected = 0;
+                palette.error = None;
+            }
+            KeyCode::Up => palette.selected = palette.selected.saturating_sub(1),
+            KeyCode::Down => palette.selected += 1,
+            KeyCode::Esc => self.overlay = Overlay::None,
+            KeyCode::Enter => match palette.command(context, &self.keymap, self.tempo) {
+                Ok(command) => self.run_command(command),
+                Err(err) => palette.error = Some(err),
+            },
+            _ => {}
+        }
+        // Keep the selection on a match as the list shrinks.
+        if let Overlay::Palette(palette) = &mut self.overlay {
+            let matches = palette.matches(context, &self.keymap).len();
+            palette.selected = palette.selected.min(matches.saturating_sub(1));
+        }
+    }
+
+    /// Runs a command from the palette, closing it unless there is more to type.
+    fn run_command(&mut self, command: Command) {
+        self.overlay = Overlay::None;
+        match command {
+            Command::Action(action) => self.perform(action),
+            Command::Toggle(param) => self.toggle(param),
+            Command::Set(param, value) => {
+                let (min, max) = self.effect_params.range(param);
+                self.set_param(param, value.clamp(min, max));
+            }
+            Command::CompleteSet(param) => {
+                self.overlay = Overlay::Palette(Palette {
+                    query: format!("set {} ", param.name()),
+                    ..Palette::default()
+                });
+            }
+        }
+    }
+
+    /// Sets the selected parameter to the typed value, clamped into its range. Text that can't
+    /// be read stays for correcting.
+    fn commit_entry(&mut self) {
+        let (Some(param), Some(entry)) = (self.selected_param(), &mut self.entry) else {
+            self.entry = None;
+            return;
+        };
+        match param.unit().parse(&entry.text, self.tempo) {
+            Ok(value) => {
+                let (min, max) = self.effect_params.range(param);
+                self.entry = None;
+                self.set_param(param, value.clamp(min, max));
+            }
+            Err(err) => entry.error = Some(err),
+        }
+    }
+
+    /// Clicking a tab opens it. Clicking a knob selects it, dragging up or down turns it, the
+    /// wheel turns it in fine steps and a double click resets it. Clicking a switch flips it.
+    fn handle_mouse_event(&mut self, mouse_event: MouseEvent) {
+        if !matches!(self.overlay, Overlay::None) {
+            return;
+        }
+        let position = Position::new(mouse_event.column, mouse_event.row);
+        match mouse_event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if let Some(tab) = self.hit_areas.tab_at(position) {
+                    self.tabs.index = tab;
+                    self.entered_tab();
+                    return;
+                }
+                let Some(knob) = self.hit_areas.knob_at(position) else {
+                    return;
+                };
+                self.param_selection.select(self.tabs.index, knob.index);
+                if knob.param.is_switch() {
+                    return self.toggle(knob.param);
+                }
+                let now = Instant::now();
+                let double_click = self
+                    .last_click
+                    .is_some_and(|click| click.is_double(knob.param, now));
+                if double_click {
+                    self.last_click = None;
+                    self.drag = None;
+                    self.set_param(knob.param, knob.param.default_value());
+                } else {
+                    self.last_click = Some(Click {
+                        param: knob.param,
+                        at: now,
+                    });
+                    self.drag = Some(Drag {
+                        param: knob.param,
+                        row: position.y,
+                        value: self.effect_params.get(knob.param),
+                    });
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                if let Some(drag) = self.drag {
+                    let range = self.effect_params.range(drag.param);
+                    self.set_param(drag.param, drag.value_at(position.y, range));
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.drag = None,
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                if let Some(knob) = self.hit_areas.knob_at(position) {
+                    self.param_selection.select(self.tabs.index, knob.index);
+                    let up = mouse_event.kind == MouseEventKind::ScrollUp;
+                    self.step_param(knob.param, StepSize::Fine, up, 1.0);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Which screen's key bindings apply on the current tab.
+    pub fn context(&self) -> Context {
+        match self.tabs.index {
+            SETTINGS_TAB => Context::Settings,
+            LOG_TAB => Context::Log,
+            _ => Context::Effects,
+        }
+    }
+
+    pub fn perform(&mut self, action: Action) {
+        match action {
+            Action::Quit => self.exit(),
+            Action::NextTab => self.next_tab(),
+            Action::PreviousTab => self.previous_tab(),
+            Action::Help => self.overlay = Overlay::Help { scroll: 0 },
+            Action::Close => self.overlay = Overlay::None,
+            Action::CommandPalette => self.overlay = Overlay::Palette(Palette::default()),
+            Action::NextParam => self.next_param(),
+            Action::PreviousParam => self.previous_param(),
+            Action::Increase => self.change_param(StepSize::Normal, true),
+            Action::Decrease => self.change_param(StepSize::Normal, false),
+            Action::IncreaseCoarse => self.change_param(StepSize::Coarse, true),
+            Action::DecreaseCoarse => self.change_param(StepSize::Coarse, false),
+            Action::IncreaseFine => self.change_param(StepSize::Fine, true),
+            Action::DecreaseFine => self.change_param(StepSize::Fine, false),
+            Action::EnterValue => {
+                if self.selected_param().is_some() {
+                    self.entry = Some(ValueEntry::default());
+                }
+            }
+            Action::ToggleBypass => self.toggle_bypass(),
+            Action::Undo => {
+                if let Some(Some(slot)) = self.history.undo(&self.effect_params) {
+                    self.snapshots.set_active(slot);
+                }
+            }
+            Action::Redo => {
+                if let Some(Some(slot)) = self.history.redo(&self.effect_params) {
+                    self.snapshots.set_active(slot);
+                }
+            }
+            Action::SwitchSnapshot => self.switch_snapshot(),
+            Action::CopySnapshot => self
+                .snapshots
+                .copy_to(self.snapshots.next(), &self.effect_params),
+            Action::NextField => self.settings.next_field(),
+            Action::PreviousField => self.settings.previous_field(),
+            Action::NextValue => self.settings.change(true),
+            Action::PreviousValue => self.settings.change(false),
+            Action::ApplySettings => self.apply_settings(),
+            Action::ReloadSettings => self.settings.load(self.engine.settings().as_ref()),
+            Action::ScrollUp => self.log_scroll += 1,
+            Action::ScrollDown => self.log_scroll = self.log_scroll.saturating_sub(1),
+            Action::PageUp => self.log_scroll += LOG_PAGE,
+            Action::PageDown => self.log_scroll = self.log_scroll.saturating_sub(LOG_PAGE),
+            Action::Oldest => self.log_scroll = usize::MAX,
+            Action::Newest => self.log_scroll = 0,
+        }
+    }
+
+    /// Asks the pipeline to rebuild the streams with the settings picked on the settings tab.
+    fn apply_settings(&mut self) {
+        if let Some(settings) = self.settings.selected() {
+            // The pipeline thread only goes away when the app is quitting.
+            let _ = self.commands.send(PipelineCommand::Reconfigure(settings));
+        }
+    }
+
     fn exit(&mut self) {
         self.running
             .store(false, std::sync::atomic::Ordering::SeqCst);
@@ -87,61 +455,90 @@ impl<'a> App<'a> {
 
     fn next_tab(&mut self) {
         self.tabs.next();
+        self.entered_tab();
     }
 
     fn previous_tab(&mut self) {
         self.tabs.previous();
+        self.entered_tab();
     }
 
-    fn decrease_param(&mut self) {
-        match self.tabs.index {
-            0 => self.change_distortion_param(-0.01),
-            //1 => self.change_delay_param(-0.1),
-            //2 => self.change_reverb_param(-0.1),
-            _ => {}
+    fn entered_tab(&mut self) {
+        if self.tabs.index == SETTINGS_TAB {
+            self.settings.load(self.engine.settings().as_ref());
         }
     }
 
-    fn increase_param(&mut self) {
-        match self.tabs.index {
-            0 => self.change_distortion_param(0.01),
-            //1 => self.change_delay_param(0.1),
-            //2 => self.change_reverb_param(0.1),
-            _ => {}
+    /// Turns the selected parameter up or down a step, faster while the key is held.
+    fn change_param(&mut self, size: StepSize, up: bool) {
+        let Some(param) = self.selected_param() else {
+            return;
+        };
+        let steps = self.acceleration.press(param, up, size);
+        self.step_param(param, size, up, steps);
+    }
+
+    /// Moves `param` by `steps` steps of `size`, staying within its range.
+    fn step_param(&mut self, param: Param, size: StepSize, up: bool, steps: f32) {
+        let amount = adjust::step(param, size) * steps;
+        let amount = if up { amount } else { -amount };
+        let (min, max) = self.effect_params.range(param);
+        let value = param
+            .unit()
+            .offset(self.effect_params.get(param), amount)
+            .clamp(min, max);
+        if value != self.effect_params.get(param) {
+            self.set_param(param, value);
         }
     }
 
-    fn change_distortion_param(&mut self, amount: f32) {
-        match self.param_selection.distortion_index {
-            0 => {
-                let current_level =
-                    self.effect_params.distortion.level.load(Ordering::Relaxed) as f32;
-                if -0.0 < current_level + amount && current_level + amount < 1.0 {
-                    self.effect_params
-                        .distortion
-                        .level
-                        .store(current_level + amount, Ordering::Relaxed);
-                }
-            }
-            1 => {
-                let current_dist = self
-                    .effect_params
-                    .distortion
-                    .distortion
-                    .load(Ordering::Relaxed) as f32;
-                if -0.01 < current_dist + amount && current_dist + amount < 1.01 {
-                    self.effect_params.distortion.distortion.store(
-                        self.effect_params
-                            .distortion
-                            .distortion
-                            .load(Ordering::Relaxed) as f32
-                            + amount,
-                        Ordering::Relaxed,
-                    );
-                }
-            }
-            _ => {}
+    /// The parameter the adjust keys and value entry work on.
+    pub fn selected_param(&self) -> Option<Param> {
+        let params = TAB_PARAMS.get(self.tabs.index)?;
+        params
+            .get(self.param_selection.index(self.tabs.index))
+            .copied()
+    }
+
+    /// Switches the effect on the current tab on or off.
+    fn toggle_bypass(&mut self) {
+        let param = match self.tabs.index {
+            0 => Param::DistortionBypass,
+            1 => Param::DelayBypass,
+            _ => return,
+        };
+        self.toggle(param);
+    }
+
+    /// Flips a switch.
+    fn toggle(&mut self, param: Param) {
+        let on = self.effect_params.get(param) >= 0.5;
+        self.set_param(param, if on { 0.0 } else { 1.0 });
+    }
+
+    /// Flips to the next snapshot slot. Undo makes the slot switched away from active again,
+    /// with the settings it had.
+    fn switch_snapshot(&mut self) {
+        let slot = SlotChange {
+            from: self.snapshots.active(),
+            to: self.snapshots.next(),
+        };
+        let changes = self.snapshots.switch(slot.to, &self.effect_params);
+        for change in &changes {
+            self.effect_params.set(change.param, change.to);
         }
+        self.history.record_switch(changes, slot);
+    }
+
+    /// Changes a parameter and records the edit so it can be undone.
+    fn set_param(&mut self, param: Param, value: f32) {
+        let from = self.effect_params.get(param);
+        self.effect_params.set(param, value);
+        self.history.record(vec![Change {
+            param,
+            from,
+            to: value,
+        }]);
     }
 
     fn next_param(&mut self) {
@@ -192,39 +589,42 @@ impl ParamSelection {
         }
     }
 
-    pub fn next(&mut self, selected_effect: usize) {
+    /// Selects the parameter at `index` on the tab of `selected_effect`.
+    pub fn select(&mut self, selected_effect: usize, index: usize) {
         match selected_effect {
-            0 => self.distortion_index = (self.distortion_index + 1) % 2,
-            1 => self.delay_index = (self.delay_index + 1) % 3,
-            2 => self.reverb_index = (self.delay_index + 1) % 3,
+            0 => self.distortion_index = index,
+            1 => self.delay_index = index,
+            2 => self.reverb_index = index,
             _ => {}
         }
     }
 
-    pub fn previous(&mut self, selected_effect: usize) {
+    /// Which parameter is selected on the tab of `selected_effect`.
+    pub fn index(&self, selected_effect: usize) -> usize {
         match selected_effect {
-            0 => {
-                if self.distortion_index > 0 {
-                    self.distortion_index -= 1;
-                } else {
-                    self.distortion_index = 3;
-                }
-            }
-            1 => {
-                if self.distortion_index > 0 {
-                    self.distortion_index -= 1;
-                } else {
-                    self.distortion_index = 3;
-                }
-            }
-            2 => {
-                if self.distortion_index > 0 {
-                    self.distortion_index -= 1;
-                } else {
-                    self.distortion_index = 3;
-                }
-            }
-            _ => {}
+            0 => self.distortion_index,
+            1 => self.delay_index,
+            2 => self.reverb_index,
+            _ => 0,
+        }
+    }
+
+    pub fn next(&mut self, selected_effect: usize) {
+        let count = TAB_PARAMS
+            .get(selected_effect)
+            .map_or(0, |params| params.len());
+        if count > 0 {
+            self.select(selected_effect, (self.index(selected_effect) + 1) % count);
+        }
+    }
+
+    pub fn previous(&mut self, selected_effect: usize) {
+        let count = TAB_PARAMS
+            .get(selected_effect)
+            .map_or(0, |params| params.len());
+        if count > 0 {
+            let index = self.index(selected_effect).min(count - 1);
+            self.select(selected_effect, (index + count - 1) % count);
         }
     }
 }
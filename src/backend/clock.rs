@@ -0,0 +1,91 @@
+// A stand-in for a duplex sound card: one thread calls the input and then the output callback a
+// period at a time, either paced by a timer or as fast as it can.
+
+use super::Callbacks;
+use assert_no_alloc::assert_no_alloc;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Supplies each period of input. Returns `false` once there is nothing left to play.
+pub type Source = Box<dyn FnMut(&mut [f32]) -> bool + Send>;
+/// Takes each period of output.
+pub type Sink = Box<dyn FnMut(&[f32]) + Send>;
+
+/// The shape of the audio the clock drives.
+#[derive(Clone, Copy, Debug)]
+pub struct ClockConfig {
+    pub input_channels: usize,
+    pub output_channels: usize,
+    pub sample_rate: u32,
+    pub period_frames: usize,
+    /// Wait a period's worth of time between periods instead of running flat out.
+    pub realtime: bool,
+}
+
+/// The clock thread. Dropping it stops the thread.
+pub struct Clock {
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Clock {
+    pub fn start(
+        config: ClockConfig,
+        mut source: Source,
+        mut sink: Sink,
+        callbacks: Callbacks,
+    ) -> anyhow::Result<Self> {
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = Arc::clone(&running);
+        let Callbacks {
+            mut input,
+            mut output,
+            error: _,
+        } = callbacks;
+
+        let thread = thread::Builder::new()
+            .name("clock".to_string())
+            .spawn(move || {
+                let mut input_block = vec![0.0; config.period_frames * config.input_channels];
+                let mut output_block = vec![0.0; config.period_frames * config.output_channels];
+                let period = Duration::from_secs_f64(
+                    config.period_frames as f64 / config.sample_rate as f64,
+                );
+                let mut next_period = Instant::now();
+
+                while thread_running.load(Ordering::Relaxed) {
+                    if !source(&mut input_block) {
+                        break;
+                    }
+                    // The callbacks get the same guard as on a real device.
+                    assert_no_alloc(|| {
+                        input(&input_block);
+                        output(&mut output_block);
+                    });
+                    sink(&output_block);
+
+                    if config.realtime {
+                        next_period += period;
+                        thread::sleep(next_period.saturating_duration_since(Instant::now()));
+                    }
+                }
+            })?;
+        Ok(Self {
+            running,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for Clock {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,271 @@
+// Real devices through cpal, including JACK when built with the `jack` feature.
+
+use super::{Backend, Callbacks, StreamHandle, StreamSetup};
+use crate::cli::{BackendKind, Opt};
+use crate::pipeline::StreamSettings;
+use crate::{devices, sample_format};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+/// Devices and formats picked by the last `configure`.
+struct Configured {
+    input: (cpal::Device, cpal::StreamConfig, cpal::SampleFormat),
+    output: (cpal::Device, cpal::StreamConfig, cpal::SampleFormat),
+}
+
+pub struct CpalBackend {
+    host: cpal::Host,
+    configured: Option<Configured>,
+}
+
+impl CpalBackend {
+    pub fn new(host: cpal::Host) -> Self {
+        Self {
+            host,
+            configured: None,
+        }
+    }
+}
+
+impl Backend for CpalBackend {
+    fn configure(&mut self, settings: &StreamSettings) -> anyhow::Result<StreamSetup> {
+        self.configured = None;
+        if settings.host != self.host.id() {
+            self.host = cpal::host_from_id(settings.host)
+                .map_err(|err| anyhow::anyhow!("{}: {err}", settings.host))?;
+        }
+        let host = &self.host;
+
+        // Find devices, falling back to the defaults if the requested ones are missing.
+        let (input_device, input_fallback) = find_device(
+            host,
+            settings.input_device.as_ref(),
+            host.default_input_device(),
+        )
+        .ok_or_else(|| anyhow::anyhow!("failed to find input device"))?;
+        let (output_device, output_fallback) = find_device(
+            host,
+            settings.output_device.as_ref(),
+            host.default_output_device(),
+        )
+        .ok_or_else(|| anyhow::anyhow!("failed to find output device"))?;
+
+        log::debug!("input device: {:?}", input_device.id());
+        log::debug!("output device: {:?}", output_device.id());
+
+        // Each device gets its own configuration. The output tries to match the input's sample
+        // rate so that no resampling is needed, but keeps its own channel count.
+        let input_config = negotiate_input_config(&input_device, settings)?;
+        let output_config =
+            negotiate_output_config(&output_device, input_config.sample_rate, settings)?;
+
+        // The devices may only support integer formats, so pick one each and convert to f32 at
+        // the stream boundary.
+        let input_format = sample_format::input_format(&input_device, &input_config)?;
+        let output_format = sample_format::output_format(&output_device, &output_config)?;
+
+        let same_device = input_device.id().ok() == output_device.id().ok();
+        let setup = StreamSetup {
+            input: input_config.clone(),
+            output: output_config.clone(),
+            same_device,
+            shares_clock: shares_clock(host, same_device),
+            fallback: input_fallback || output_fallback,
+            host_name: host.id().name().to_string(),
+            input_name: devices::device_name(&input_device),
+            output_name: devices::device_name(&output_device),
+        };
+        self.configured = Some(Configured {
+            input: (input_device, input_config, input_format),
+            output: (output_device, output_config, output_format),
+        });
+        Ok(setup)
+    }
+
+    fn start(&mut self, callbacks: Callbacks) -> anyhow::Result<StreamHandle> {
+        let Configured {
+            input: (input_device, input_config, input_format),
+            output: (output_device, output_config, output_format),
+        } = self
+            .configured
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("streams started before being configured"))?;
+        let Callbacks {
+            input,
+            output,
+            error,
+        } = callbacks;
+        // Both streams report to the same place, so share the error callback between them.
+        let error = std::sync::Arc::new(std::sync::Mutex::new(error));
+        let output_error = std::sync::Arc::clone(&error);
+
+        log::debug!("building input stream with {input_format} samples and {input_config:?}");
+        log::debug!("building output stream with {output_format} samples and {output_config:?}");
+        let input_stream = sample_format::build_input_stream(
+            input_device,
+            input_config,
+            *input_format,
+            input,
+            move |err| {
+                if let Ok(mut error) = error.lock() {
+                    error(err);
+                }
+            },
+        )?;
+        let output_stream = sample_format::build_output_stream(
+            output_device,
+            output_config,
+            *output_format,
+            output,
+            move |err| {
+                if let Ok(mut error) = output_error.lock() {
+                    error(err);
+                }
+            },
+        )?;
+        input_stream.play()?;
+        output_stream.play()?;
+        Ok(Box::new((input_stream, output_stream)))
+    }
+
+    fn host_id(&self) -> cpal::HostId {
+        self.host.id()
+    }
+
+    fn devices_present(&self, settings: &StreamSettings) -> bool {
+        settings.host != self.host.id() || requested_devices_present(&self.host, settings)
+    }
+}
+
+/// The host picked on the command line: JACK for `--jack` or `--backend jack`, otherwise the
+/// platform default.
+pub fn select_host(opt: &Opt) -> anyhow::Result<cpal::Host> {
+    if opt.jack || opt.backend == BackendKind::Jack {
+        jack_host()
+    } else {
+        Ok(cpal::default_host())
+    }
+}
+
+/// The JACK host. Only there on Unix builds with the `jack` feature.
+#[cfg(all(
+    any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd"
+    ),
+    feature = "jack"
+))]
+pub fn jack_host() -> anyhow::Result<cpal::Host> {
+    cpal::host_from_id(cpal::HostId::Jack)
+        .map_err(|err| anyhow::anyhow!("JACK host unavailable: {err}"))
+}
+
+#[cfg(any(
+    not(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd"
+    )),
+    not(feature = "jack")
+))]
+pub fn jack_host() -> anyhow::Result<cpal::Host> {
+    anyhow::bail!("built without JACK support, rebuild with --features jack")
+}
+
+/// Whether input and output are guaranteed to run off the same clock.
+#[cfg(all(
+    any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd"
+    ),
+    feature = "jack"
+))]
+fn shares_clock(host: &cpal::Host, same_device: bool) -> bool {
+    same_device || host.id() == cpal::HostId::Jack
+}
+
+#[cfg(any(
+    not(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd"
+    )),
+    not(feature = "jack")
+))]
+fn shares_clock(_host: &cpal::Host, same_device: bool) -> bool {
+    same_device
+}
+
+/// Looks up the requested device, or uses `default` when there is no request or the requested
+/// device is missing. The flag says whether the default stands in for a missing device.
+pub fn find_device(
+    host: &cpal::Host,
+    id: Option<&cpal::DeviceId>,
+    default: Option<cpal::Device>,
+) -> Option<(cpal::Device, bool)> {
+    match id {
+        Some(id) => match host.device_by_id(id) {
+            Some(device) => Some((device, false)),
+            None => default.map(|device| (device, true)),
+        },
+        None => default.map(|device| (device, false)),
+    }
+}
+
+fn requested_devices_present(host: &cpal::Host, settings: &StreamSettings) -> bool {
+    [&settings.input_device, &settings.output_device]
+        .into_iter()
+        .flatten()
+        .all(|id| host.device_by_id(id).is_some())
+}
+
+/// Uses the input device's default configuration, switched to the requested sample rate and
+/// buffer size if there are any.
+pub fn negotiate_input_config(
+    device: &cpal::Device,
+    settings: &StreamSettings,
+) -> anyhow::Result<cpal::StreamConfig> {
+    let default = device.default_input_config()?;
+    let supported = match settings.sample_rate {
+        Some(rate) => device
+            .supported_input_configs()?
+            .filter(|range| range.channels() == default.channels())
+            .find_map(|range| range.try_with_sample_rate(rate))
+            .ok_or_else(|| anyhow::anyhow!("input device doesn't support {rate} Hz"))?,
+        None => default,
+    };
+    let mut config: cpal::StreamConfig = supported.into();
+    if let Some(frames) = settings.buffer_size {
+        config.buffer_size = cpal::BufferSize::Fixed(frames);
+    }
+    Ok(config)
+}
+
+/// Uses the output device's default configuration, switched to `sample_rate` if the device's
+/// default format supports it, and to the requested buffer size if there is one.
+pub fn negotiate_output_config(
+    device: &cpal::Device,
+    sample_rate: cpal::SampleRate,
+    settings: &StreamSettings,
+) -> anyhow::Result<cpal::StreamConfig> {
+    let default = device.default_output_config()?;
+    let matching_rate = device.supported_output_configs()?.find_map(|range| {
+        if range.channels() == default.channels()
+            && range.sample_format() == default.sample_format()
+        {
+            range.try_with_sample_rate(sample_rate)
+        } else {
+            None
+        }
+    });
+    let mut config: cpal::StreamConfig = matching_rate.unwrap_or(default).into();
+    if let Some(frames) = settings.buffer_size {
+        config.buffer_size = cpal::BufferSize::Fixed(frames);
+    }
+    Ok(config)
+}
@@ -0,0 +1,183 @@
+// A backend that plays a WAV file into the pipeline and records what comes out into another one.
+//
+// The output file gets the input's sample rate and channel count as 32-bit float samples. After
+// the input runs out, the clock keeps going for `TAIL` so echoes and the pipeline's own latency
+// make it into the recording, then the backend reports itself finished.
+
+use super::clock::{Clock, ClockConfig};
+use super::{Backend, Callbacks, StreamHandle, StreamSetup};
+use crate::pipeline::StreamSettings;
+use std::path::{Path, PathBuf};
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::time::Duration;
+
+const DEFAULT_PERIOD_FRAMES: u32 = 256;
+/// How long to keep recording after the input file ends.
+const TAIL: Duration = Duration::from_secs(3);
+
+pub struct FileBackend {
+    samples: Arc<Vec<f32>>,
+    channels: u16,
+    sample_rate: u32,
+    input: PathBuf,
+    output: PathBuf,
+    realtime: bool,
+    period_frames: u32,
+    finished: Arc<AtomicBool>,
+}
+
+impl FileBackend {
+    /// Reads all of `input` up front. With `realtime` off the files are processed as fast as the
+    /// pipeline can go.
+    pub fn new(input: &Path, output: &Path, realtime: bool) -> anyhow::Result<Self> {
+        let reader = hound::WavReader::open(input)
+            .map_err(|err| anyhow::anyhow!("failed to open {}: {err}", input.display()))?;
+        let spec = reader.spec();
+        let samples: Vec<f32> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<_, _>>()?,
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1_i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .into_samples::<i32>()
+                    .map(|sample| sample.map(|sample| sample as f32 * scale))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+        Ok(Self {
+            samples: Arc::new(samples),
+            channels: spec.channels,
+            sample_rate: spec.sample_rate,
+            input: input.to_path_buf(),
+            output: output.to_path_buf(),
+            realtime,
+            period_frames: DEFAULT_PERIOD_FRAMES,
+            finished: Arc::new(AtomicBool::new(false)),
+        })
+    }
+}
+
+impl Backend for FileBackend {
+    fn configure(&mut self, settings: &StreamSettings) -> anyhow::Result<StreamSetup> {
+        self.period_frames = settings.buffer_size.unwrap_or(DEFAULT_PERIOD_FRAMES);
+        let config = cpal::StreamConfig {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            buffer_size: cpal::BufferSize::Fixed(self.period_frames),
+        };
+        Ok(StreamSetup {
+            input: config.clone(),
+            output: config,
+            same_device: true,
+            shares_clock: true,
+            fallback: false,
+            host_name: "file".to_string(),
+            input_name: file_name(&self.input),
+            output_name: file_name(&self.output),
+        })
+    }
+
+    fn start(&mut self, callbacks: Callbacks) -> anyhow::Result<StreamHandle> {
+        if self.finished() {
+            anyhow::bail!("the input file has already been played");
+        }
+        let spec = hound::WavSpec {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let writer = hound::WavWriter::create(&self.output, spec)
+            .map_err(|err| anyhow::anyhow!("failed to create {}: {err}", self.output.display()))?;
+
+        // The source says when the input and tail are used up; the recording only counts as
+        // finished once the writer holding it is closed.
+        let exhausted = Arc::new(AtomicBool::new(false));
+        let samples = Arc::clone(&self.samples);
+        let tail = (TAIL.as_secs_f64() * self.sample_rate as f64) as usize * self.channels as usize;
+        let mut position = 0;
+        let source_exhausted = Arc::clone(&exhausted);
+        let source = Box::new(move |block: &mut [f32]| {
+            if position >= samples.len() + tail {
+                source_exhausted.store(true, Ordering::Relaxed);
+                return false;
+            }
+            let start = position.min(samples.len());
+            let end = (position + block.len()).min(samples.len());
+            let (head, rest) = block.split_at_mut(end - start);
+            head.copy_from_slice(&samples[start..end]);
+            rest.fill(0.0);
+            position += block.len();
+            true
+        });
+
+        let mut recording = Recording {
+            writer: Some(writer),
+            exhausted,
+            finished: Arc::clone(&self.finished),
+        };
+        let sink = Box::new(move |block: &[f32]| recording.write(block));
+
+        let clock = Clock::start(
+            ClockConfig {
+                input_channels: self.channels as usize,
+                output_channels: self.channels as usize,
+                sample_rate: self.sample_rate,
+                period_frames: self.period_frames as usize,
+                realtime: self.realtime,
+            },
+            source,
+            sink,
+            callbacks,
+        )?;
+        Ok(Box::new(clock))
+    }
+
+    fn finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+}
+
+/// The output file being written. Closing it marks the backend finished if the whole input made
+/// it through.
+struct Recording {
+    writer: Option<hound::WavWriter<std::io::BufWriter<std::fs::File>>>,
+    exhausted: Arc<AtomicBool>,
+    finished: Arc<AtomicBool>,
+}
+
+impl Recording {
+    fn write(&mut self, block: &[f32]) {
+        if let Some(writer) = &mut self.writer {
+            for &sample in block {
+                if let Err(err) = writer.write_sample(sample) {
+                    log::error!("failed to write output file: {err}");
+                    self.writer = None;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.take()
+            && let Err(err) = writer.finalize()
+        {
+            log::error!("failed to finish output file: {err}");
+        }
+        if self.exhausted.load(Ordering::Relaxed) {
+            self.finished.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,92 @@
+// Where the pipeline's audio comes from and goes to.
+//
+// The pipeline only ever sees f32 callbacks and stream configurations, so anything that can call
+// them will do: cpal devices (including JACK through cpal), a null backend driven by a timer, or
+// WAV files. The last two need no sound card, which is what the integration tests run on.
+
+pub mod clock;
+pub mod cpal_backend;
+pub mod file;
+pub mod null;
+
+use crate::cli::{BackendKind, Opt};
+use crate::pipeline::StreamSettings;
+use std::any::Any;
+
+/// Called with every block of input, as interleaved f32 samples.
+pub type InputCallback = Box<dyn FnMut(&[f32]) + Send>;
+/// Called to fill every block of output with interleaved f32 samples.
+pub type OutputCallback = Box<dyn FnMut(&mut [f32]) + Send>;
+/// Called when a stream fails.
+pub type ErrorCallback = Box<dyn FnMut(cpal::StreamError) + Send>;
+
+/// Running streams. Dropping the handle stops them.
+pub type StreamHandle = Box<dyn Any>;
+
+/// The stream configurations a backend settled on for some settings.
+#[derive(Clone, Debug)]
+pub struct StreamSetup {
+    pub input: cpal::StreamConfig,
+    pub output: cpal::StreamConfig,
+    /// Whether input and output come from the same device.
+    pub same_device: bool,
+    /// Whether input and output are guaranteed to run off the same clock.
+    pub shares_clock: bool,
+    /// Whether a default device stands in for a requested one that is missing.
+    pub fallback: bool,
+    /// Names of the host and devices, for showing.
+    pub host_name: String,
+    pub input_name: String,
+    pub output_name: String,
+}
+
+pub struct Callbacks {
+    pub input: InputCallback,
+    pub output: OutputCallback,
+    pub error: ErrorCallback,
+}
+
+pub trait Backend: Send {
+    /// Picks devices and stream configurations for `settings`. The next `start` uses them.
+    fn configure(&mut self, settings: &StreamSettings) -> anyhow::Result<StreamSetup>;
+
+    /// Starts the input and output streams set up by the last `configure`.
+    fn start(&mut self, callbacks: Callbacks) -> anyhow::Result<StreamHandle>;
+
+    /// The cpal host the settings refer to.
+    fn host_id(&self) -> cpal::HostId {
+        cpal::default_host().id()
+    }
+
+    /// Whether every device `settings` asks for is there, so a fallback can be undone.
+    fn devices_present(&self, _settings: &StreamSettings) -> bool {
+        true
+    }
+
+    /// Whether the backend has run out of audio for good, e.g. at the end of an input file.
+    fn finished(&self) -> bool {
+        false
+    }
+}
+
+/// Creates the backend picked on the command line.
+pub fn from_opt(opt: &Opt) -> anyhow::Result<Box<dyn Backend>> {
+    let backend: Box<dyn Backend> = match opt.backend {
+        BackendKind::Cpal | BackendKind::Jack => Box::new(cpal_backend::CpalBackend::new(
+            cpal_backend::select_host(opt)?,
+        )),
+        BackendKind::Null => Box::new(null::NullBackend::new()),
+        BackendKind::File => {
+            let input = opt
+                .input_file
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("the file backend needs --input-file"))?;
+            let output = opt
+                .output_file
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("the file backend needs --output-file"))?;
+            Box::new(file::FileBackend::new(input, output, !opt.fast)?)
+        }
+    };
+    Ok(backend)
+}
@@ -0,0 +1,129 @@
+// A backend without any devices: silence in, output thrown away, driven by a timer at the
+// requested rate and buffer size. A `NullControl` lets tests unplug the requested devices and
+// fail the running streams, to exercise the pipeline's recovery.
+
+use super::clock::{Clock, ClockConfig};
+use super::{Backend, Callbacks, StreamHandle, StreamSetup};
+use crate::pipeline::StreamSettings;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+const DEFAULT_PERIOD_FRAMES: u32 = 256;
+const CHANNELS: u16 = 2;
+
+/// What a test can change about a running `NullBackend`.
+pub struct NullControl {
+    /// Whether the devices asked for are there. Without them the backend reports a fallback.
+    pub devices_present: AtomicBool,
+    /// Set to fail the running streams once.
+    pub fail: AtomicBool,
+    /// How many times the streams were started.
+    pub starts: AtomicUsize,
+}
+
+impl NullControl {
+    pub fn new() -> Self {
+        Self {
+            devices_present: AtomicBool::new(true),
+            fail: AtomicBool::new(false),
+            starts: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Default for NullControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct NullBackend {
+    config: Option<ClockConfig>,
+    control: Arc<NullControl>,
+}
+
+impl NullBackend {
+    pub fn new() -> Self {
+        Self::with_control(Arc::new(NullControl::new()))
+    }
+
+    pub fn with_control(control: Arc<NullControl>) -> Self {
+        Self {
+            config: None,
+            control,
+        }
+    }
+}
+
+impl Default for NullBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backend for NullBackend {
+    fn configure(&mut self, settings: &StreamSettings) -> anyhow::Result<StreamSetup> {
+        let sample_rate = settings.sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE);
+        let period_frames = settings.buffer_size.unwrap_or(DEFAULT_PERIOD_FRAMES);
+        self.config = Some(ClockConfig {
+            input_channels: CHANNELS as usize,
+            output_channels: CHANNELS as usize,
+            sample_rate,
+            period_frames: period_frames as usize,
+            realtime: true,
+        });
+        let config = cpal::StreamConfig {
+            channels: CHANNELS,
+            sample_rate,
+            buffer_size: cpal::BufferSize::Fixed(period_frames),
+        };
+        Ok(StreamSetup {
+            input: config.clone(),
+            output: config,
+            same_device: true,
+            shares_clock: true,
+            fallback: (settings.input_device.is_some() || settings.output_device.is_some())
+                && !self.devices_present(settings),
+            host_name: "null".to_string(),
+            input_name: "silence".to_string(),
+            output_name: "nowhere".to_string(),
+        })
+    }
+
+    fn start(&mut self, callbacks: Callbacks) -> anyhow::Result<StreamHandle> {
+        let config = self
+            .config
+            .ok_or_else(|| anyhow::anyhow!("null backend started before being configured"))?;
+        self.control.starts.fetch_add(1, Ordering::SeqCst);
+        let Callbacks {
+            input,
+            output,
+            mut error,
+        } = callbacks;
+        let control = Arc::clone(&self.control);
+        let clock = Clock::start(
+            config,
+            Box::new(move |block| {
+                if control.fail.swap(false, Ordering::SeqCst) {
+                    error(cpal::StreamError::DeviceNotAvailable);
+                }
+                block.fill(0.0);
+                true
+            }),
+            Box::new(|_| {}),
+            Callbacks {
+                input,
+                output,
+                error: Box::new(|_| {}),
+            },
+        )?;
+        Ok(Box::new(clock))
+    }
+
+    fn devices_present(&self, _settings: &StreamSettings) -> bool {
+        self.control.devices_present.load(Ordering::SeqCst)
+    }
+}
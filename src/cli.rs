@@ -0,0 +1,157 @@
+// Command line options.
+
+use clap::{Parser, Subcommand, ValueEnum};
+use log::LevelFilter;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(version, about = "TUI audio effects suite", long_about = None)]
+pub struct Opt {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// The input audio device to use
+    #[arg(short, long, value_name = "IN")]
+    pub input_device: Option<String>,
+
+    /// The output audio device to use
+    #[arg(short, long, value_name = "OUT")]
+    pub output_device: Option<String>,
+
+    /// Specify the delay between input and output. Not used in duplex mode
+    #[arg(
+        short,
+        long,
+        value_name = "DELAY_MS",
+        default_value_t = 150.0,
+        value_parser = positive_ms
+    )]
+    pub latency: f32,
+
+    /// Grow the delay between input and output after underruns and shrink it again while
+    /// playback is stable
+    #[arg(short, long)]
+    pub adaptive_latency: bool,
+
+    /// Don't adjust the resampling ratio to follow clock drift between the input and output devices
+    #[arg(long)]
+    pub no_drift_compensation: bool,
+
+    /// Sample rate to run the streams at instead of the device default
+    #[arg(short, long, value_name = "HZ", value_parser = clap::value_parser!(u32).range(1..))]
+    pub sample_rate: Option<u32>,
+
+    /// Fixed buffer size in frames instead of the host default
+    #[arg(short, long, value_name = "FRAMES", value_parser = clap::value_parser!(u32).range(1..))]
+    pub buffer_size: Option<u32>,
+
+    /// Process input and write output within one period. Needs JACK or the same device for input
+    /// and output, and a --buffer-size to set the period
+    #[arg(short, long, requires = "buffer_size")]
+    pub duplex: bool,
+
+    /// The config file to read instead of audio_oxidiser/config.toml in the user's config
+    /// directory
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Where to write the log, rotated once it gets large. Defaults to
+    /// audio_oxidiser/audio_oxidiser.log in the user's state directory
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Least severe messages to log: error, warn, info, debug or trace
+    #[arg(long, value_name = "LEVEL", default_value_t = LevelFilter::Info)]
+    pub log_level: LevelFilter,
+
+    /// Where audio comes from and goes to
+    #[arg(long, value_enum, default_value_t = BackendKind::Cpal)]
+    pub backend: BackendKind,
+
+    /// Use the JACK host, same as --backend jack
+    #[arg(short, long)]
+    pub jack: bool,
+
+    /// The WAV file the file backend plays into the effects
+    #[arg(long, value_name = "PATH")]
+    pub input_file: Option<PathBuf>,
+
+    /// The WAV file the file backend records the output to
+    #[arg(long, value_name = "PATH")]
+    pub output_file: Option<PathBuf>,
+
+    /// Process files as fast as possible instead of in real time
+    #[arg(long)]
+    pub fast: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// List the available audio hosts
+    ListHosts {
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List the devices of every available host
+    ListDevices {
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show the default and supported stream configs of a device
+    DeviceInfo {
+        /// The device id, as printed by list-devices
+        id: String,
+
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Play a test signal, record it back through a loopback from the output to the input and
+    /// report the round-trip latency
+    MeasureLatency {
+        /// The test signal to play
+        #[arg(long, value_enum, default_value_t = Signal::Mls)]
+        signal: Signal,
+
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Signal {
+    /// A single click
+    Impulse,
+    /// A maximum length sequence, which stands out from background noise far better
+    Mls,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackendKind {
+    /// Sound cards through the platform's default host
+    Cpal,
+    /// JACK, needs a build with --features jack
+    Jack,
+    /// No devices, silence in and output discarded, driven by a timer
+    Null,
+    /// Play --input-file and record to --output-file
+    File,
+}
+
+/// Reads a time in milliseconds that has to be more than zero.
+fn positive_ms(text: &str) -> Result<f32, String> {
+    let ms: f32 = text
+        .parse()
+        .map_err(|_| format!("{text:?} is not a number"))?;
+    if ms > 0.0 && ms.is_finite() {
+        Ok(ms)
+    } else {
+        Err("must be more than 0".to_string())
+    }
+}
@@ -0,0 +1,119 @@
+// The user's config file, in TOML. Everything in it is optional.
+//
+// [keys]
+// undo = ["u", "ctrl+z"]
+//
+// [ui]
+// frame_rate = 30
+// theme = "high-contrast"
+// tempo = 120
+//
+// [themes.mine]
+// title = "bold cyan"
+
+use crate::keymap::Keymap;
+use crate::theme::Theme;
+use crate::units::DEFAULT_TEMPO;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    /// Keys for actions, replacing the default ones.
+    keys: HashMap<String, Vec<String>>,
+    ui: UiFile,
+    /// Themes by name, each a style for some of the theme's slots.
+    themes: HashMap<String, HashMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct UiFile {
+    /// Redraws per second.
+    frame_rate: u32,
+    /// A built-in theme or one from `[themes]`.
+    theme: String,
+    /// Beats per minute, for times typed in and shown as note lengths.
+    tempo: f32,
+}
+
+impl Default for UiFile {
+    fn default() -> Self {
+        Self {
+            frame_rate: 30,
+            theme: "default".to_string(),
+            tempo: DEFAULT_TEMPO,
+        }
+    }
+}
+
+/// Highest frame rate allowed, well beyond what a terminal can show.
+const MAX_FRAME_RATE: u32 = 240;
+/// The range of tempos allowed, in beats per minute.
+const TEMPO_RANGE: std::ops::RangeInclusive<f32> = 20.0..=400.0;
+
+pub struct Config {
+    pub keymap: Keymap,
+    /// How many times a second the UI redraws to show live data.
+    pub frame_rate: u32,
+    pub theme: Theme,
+    /// Beats per minute that note lengths are timed against.
+    pub tempo: f32,
+}
+
+impl Config {
+    /// Reads the config from `path`, or from the default location if there is no path. Only a
+    /// missing file at the default location is fine, anything else wrong with the file is an
+    /// error.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (Some(path.to_path_buf()), true),
+            None => (default_path(), false),
+        };
+        let file = match path {
+            Some(path) => match std::fs::read_to_string(&path) {
+                Ok(text) => {
+                    log::info!("loading config from {}", path.display());
+                    toml::from_str(&text)
+                        .map_err(|err| anyhow::anyhow!("{}: {err}", path.display()))?
+                }
+                Err(err) if !required && err.kind() == std::io::ErrorKind::NotFound => {
+                    ConfigFile::default()
+                }
+                Err(err) => anyhow::bail!("failed to read {}: {err}", path.display()),
+            },
+            None => ConfigFile::default(),
+        };
+        if !(1..=MAX_FRAME_RATE).contains(&file.ui.frame_rate) {
+            anyhow::bail!(
+                "frame_rate in [ui] must be from 1 to {MAX_FRAME_RATE}, not {}",
+                file.ui.frame_rate
+            );
+        }
+        if !TEMPO_RANGE.contains(&file.ui.tempo) {
+            anyhow::bail!(
+                "tempo in [ui] must be from {} to {} BPM, not {}",
+                TEMPO_RANGE.start(),
+                TEMPO_RANGE.end(),
+                file.ui.tempo
+            );
+        }
+        Ok(Self {
+            keymap: Keymap::new(&file.keys)?,
+            frame_rate: file.ui.frame_rate,
+            theme: Theme::load(&file.ui.theme, &file.themes)?,
+            tempo: file.ui.tempo,
+        })
+    }
+}
+
+/// `audio_oxidiser/config.toml` in `$XDG_CONFIG_HOME`, or in `~/.config` if that isn't set.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("audio_oxidiser").join("config.toml"))
+}
@@ -0,0 +1,122 @@
+// Enumeration of audio hosts, devices and the stream configurations they support.
+
+use cpal::traits::{DeviceTrait, HostTrait};
+
+/// Sample rates offered when a device supports a range of them.
+pub const COMMON_SAMPLE_RATES: [u32; 11] = [
+    8_000, 11_025, 16_000, 22_050, 32_000, 44_100, 48_000, 88_200, 96_000, 176_400, 192_000,
+];
+
+/// Buffer sizes, in frames, offered when a device supports a range of them.
+pub const COMMON_BUFFER_SIZES: [u32; 9] = [16, 32, 64, 128, 256, 512, 1024, 2048, 4096];
+
+/// A device and everything it reports supporting.
+pub struct DeviceInfo {
+    pub id: cpal::DeviceId,
+    pub name: String,
+    pub default_input: Option<cpal::SupportedStreamConfig>,
+    pub default_output: Option<cpal::SupportedStreamConfig>,
+    pub input_configs: Vec<cpal::SupportedStreamConfigRange>,
+    pub output_configs: Vec<cpal::SupportedStreamConfigRange>,
+}
+
+impl DeviceInfo {
+    /// Queries `device`, or returns `None` if it can't report an id.
+    pub fn from_device(device: &cpal::Device) -> Option<Self> {
+        let id = device.id().ok()?;
+        Some(Self {
+            name: device_name(device),
+            id,
+            default_input: device.default_input_config().ok(),
+            default_output: device.default_output_config().ok(),
+            input_configs: device
+                .supported_input_configs()
+                .map(Iterator::collect)
+                .unwrap_or_default(),
+            output_configs: device
+                .supported_output_configs()
+                .map(Iterator::collect)
+                .unwrap_or_default(),
+        })
+    }
+
+    pub fn is_input(&self) -> bool {
+        !self.input_configs.is_empty()
+    }
+
+    pub fn is_output(&self) -> bool {
+        !self.output_configs.is_empty()
+    }
+}
+
+/// Lists every device on `host`. Devices that can't report an id are skipped, since there would
+/// be no way to select them.
+pub fn list_devices(host: &cpal::Host) -> anyhow::Result<Vec<DeviceInfo>> {
+    Ok(host
+        .devices()?
+        .filter_map(|device| DeviceInfo::from_device(&device))
+        .collect())
+}
+
+/// The common sample rates at least one of `configs` supports.
+pub fn supported_sample_rates(configs: &[cpal::SupportedStreamConfigRange]) -> Vec<u32> {
+    COMMON_SAMPLE_RATES
+        .into_iter()
+        .filter(|&rate| {
+            configs
+                .iter()
+                .any(|range| range.min_sample_rate() <= rate && rate <= range.max_sample_rate())
+        })
+        .collect()
+}
+
+/// The common buffer sizes at least one of `configs` supports. Devices that can't tell are
+/// assumed to support all of them.
+pub fn supported_buffer_sizes(configs: &[cpal::SupportedStreamConfigRange]) -> Vec<u32> {
+    COMMON_BUFFER_SIZES
+        .into_iter()
+        .filter(|&frames| {
+            configs.iter().any(|range| match *range.buffer_size() {
+                cpal::SupportedBufferSize::Range { min, max } => min <= frames && frames <= max,
+                cpal::SupportedBufferSize::Unknown => true,
+            })
+        })
+        .collect()
+}
+
+/// One-line summary of a default configuration, e.g. `2ch f32 48000 Hz`.
+pub fn describe_config(config: &cpal::SupportedStreamConfig) -> String {
+    format!(
+        "{}ch {} {} Hz",
+        config.channels(),
+        config.sample_format(),
+        config.sample_rate()
+    )
+}
+
+/// One-line summary of a supported configuration range, e.g. `2ch f32 44100-192000 Hz`.
+pub fn describe_range(range: &cpal::SupportedStreamConfigRange) -> String {
+    let rates = if range.min_sample_rate() == range.max_sample_rate() {
+        format!("{} Hz", range.min_sample_rate())
+    } else {
+        format!("{}-{} Hz", range.min_sample_rate(), range.max_sample_rate())
+    };
+    let buffer = match *range.buffer_size() {
+        cpal::SupportedBufferSize::Range { min, max } => format!(", buffer {min}-{max}"),
+        cpal::SupportedBufferSize::Unknown => String::new(),
+    };
+    format!(
+        "{}ch {} {rates}{buffer}",
+        range.channels(),
+        range.sample_format()
+    )
+}
+
+/// The name a device describes itself with, or its id if it has none.
+pub fn device_name(device: &cpal::Device) -> String {
+    match (device.description(), device.id()) {
+        (Ok(description), _) => description.name().to_string(),
+        (Err(_), Ok(id)) => id.1,
+        (Err(_), Err(_)) => "unknown device".to_string(),
+    }
+}
@@ -0,0 +1,90 @@
+// Clock drift compensation between separately clocked input and output devices.
+//
+// Two devices running at the same nominal rate never quite agree, so the ring buffer between them
+// slowly fills up or drains. The compensator watches the fill level and nudges the resampling
+// ratio to hold it at the initial prefill.
+
+/// Largest relative ratio adjustment in either direction. Real clock drift is well under 0.1%, so
+/// anything past this is a stalled stream rather than drift and shouldn't be chased.
+const MAX_ADJUSTMENT: f64 = 0.005;
+
+/// How quickly the smoothed fill level follows new readings, per update. Callbacks push in bursts,
+/// so single readings are too noisy to act on.
+const FILL_SMOOTHING: f64 = 0.01;
+
+const PROPORTIONAL_GAIN: f64 = 0.01;
+const INTEGRAL_GAIN: f64 = 0.000_002;
+
+pub struct DriftCompensator {
+    target_fill: f64,
+    smoothed_fill: f64,
+    integral: f64,
+}
+
+impl DriftCompensator {
+    /// `target_fill` is the ring buffer fill level, in samples, to hold.
+    pub fn new(target_fill: usize) -> Self {
+        let target_fill = target_fill.max(1) as f64;
+        Self {
+            target_fill,
+            smoothed_fill: target_fill,
+            integral: 0.0,
+        }
+    }
+
+    /// Moves the fill level to hold, e.g. when the latency adapts.
+    pub fn set_target(&mut self, target_fill: usize) {
+        self.target_fill = target_fill.max(1) as f64;
+    }
+
+    /// Takes the current fill level and returns the relative resampling ratio to use. A buffer
+    /// emptier than the target gives a ratio above 1.0 so that more output is produced.
+    pub fn update(&mut self, fill: usize) -> f64 {
+        self.smoothed_fill += FILL_SMOOTHING * (fill as f64 - self.smoothed_fill);
+        let error = (self.target_fill - self.smoothed_fill) / self.target_fill;
+
+        // The integral term settles on the actual drift between the clocks, the proportional
+        // term pulls the fill level back to the target.
+        self.integral =
+            (self.integral + INTEGRAL_GAIN * error).clamp(-MAX_ADJUSTMENT, MAX_ADJUSTMENT);
+        1.0 + (PROPORTIONAL_GAIN * error + self.integral).clamp(-MAX_ADJUSTMENT, MAX_ADJUSTMENT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settles_on_the_drift_between_the_clocks() {
+        // The input clock runs 0.02% fast, so the resampler has to make up for it.
+        let drift = 1.0002;
+        let target = 4096;
+        let mut compensator = DriftCompensator::new(target);
+        let mut fill = target as f64;
+        let mut ratio = 1.0;
+        for _ in 0..200_000 {
+            fill += 256.0 * drift * ratio - 256.0;
+            ratio = compensator.update(fill as usize);
+        }
+        assert!((ratio * drift - 1.0).abs() < 1e-5, "ratio was {ratio}");
+        assert!(
+            (fill - target as f64).abs() < target as f64 * 0.01,
+            "fill was {fill}"
+        );
+    }
+
+    #[test]
+    fn adjustment_is_clamped() {
+        let mut compensator = DriftCompensator::new(4096);
+        let ratios: Vec<f64> = (0..100_000).map(|_| compensator.update(0)).collect();
+        assert!(ratios.iter().all(|ratio| *ratio <= 1.0 + MAX_ADJUSTMENT));
+        assert_eq!(ratios.last(), Some(&(1.0 + MAX_ADJUSTMENT)));
+
+        let mut compensator = DriftCompensator::new(4096);
+        for _ in 0..100_000 {
+            compensator.update(1_000_000);
+        }
+        assert_eq!(compensator.update(1_000_000), 1.0 - MAX_ADJUSTMENT);
+    }
+}
@@ -1,17 +1,95 @@
+use crate::units::Unit;
 use portable_atomic::AtomicF32;
-use std::sync::{
-    Arc,
-    atomic::{AtomicBool, Ordering},
-};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 
-pub enum EffectType {
-    Distortion(DistortionParams),
-    Delay(DelayParams),
+/// A parameter the UI can change, so edits can be recorded and played back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Param {
+    DistortionBypass,
+    DistortionLevel,
+    DistortionDrive,
+    DistortionShape,
+    DelayBypass,
+    DelayTime,
+    DelayDecay,
+    DelayMode,
+    DelayFilter,
+}
+
+impl Param {
+    pub const ALL: [Param; 9] = [
+        Param::DistortionBypass,
+        Param::DistortionLevel,
+        Param::DistortionDrive,
+        Param::DistortionShape,
+        Param::DelayBypass,
+        Param::DelayTime,
+        Param::DelayDecay,
+        Param::DelayMode,
+        Param::DelayFilter,
+    ];
+
+    /// The value a fresh set of parameters starts with.
+    pub fn default_value(self) -> f32 {
+        EffectParams::new().get(self)
+    }
+
+    /// The name used in commands, e.g. `delay.time`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Param::DistortionBypass => "distortion.bypass",
+            Param::DistortionLevel => "distortion.level",
+            Param::DistortionDrive => "distortion.drive",
+            Param::DistortionShape => "distortion.shape",
+            Param::DelayBypass => "delay.bypass",
+            Param::DelayTime => "delay.time",
+            Param::DelayDecay => "delay.decay",
+            Param::DelayMode => "delay.mode",
+            Param::DelayFilter => "delay.filter",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Param> {
+        Param::ALL.into_iter().find(|param| param.name() == name)
+    }
+
+    /// The name shown on the parameter's control.
+    pub fn label(self) -> &'static str {
+        match self {
+            Param::DistortionBypass | Param::DelayBypass => "Bypass",
+            Param::DistortionLevel => "Volume",
+            Param::DistortionDrive => "Distortion",
+            Param::DistortionShape => "Shape",
+            Param::DelayTime => "Time",
+            Param::DelayDecay => "Decay",
+            Param::DelayMode => "Mode",
+            Param::DelayFilter => "Filter",
+        }
+    }
+
+    pub fn unit(self) -> Unit {
+        match self {
+            Param::DistortionBypass | Param::DelayBypass => Unit::Switch,
+            Param::DistortionLevel => Unit::Gain,
+            Param::DistortionDrive | Param::DelayDecay => Unit::Percent,
+            Param::DelayTime => Unit::Seconds,
+            Param::DistortionShape => Unit::Choice(Shape::NAMES),
+            Param::DelayMode => Unit::Choice(DelayMode::NAMES),
+            Param::DelayFilter => Unit::Choice(DelayFilter::NAMES),
+        }
+    }
+
+    /// Whether the parameter is an on/off switch rather than a continuous value.
+    pub fn is_switch(self) -> bool {
+        matches!(self, Param::DistortionBypass | Param::DelayBypass)
+    }
 }
 
 pub struct EffectParams {
     pub distortion: DistortionParams,
     pub delay: DelayParams,
+    /// Bumped by every `set`, so the UI can tell something changed without comparing values.
+    changes: AtomicU64,
 }
 
 impl EffectParams {
@@ -19,16 +97,91 @@ impl EffectParams {
         Self {
             distortion: DistortionParams::new(),
             delay: DelayParams::new(),
+            changes: AtomicU64::new(0),
+        }
+    }
+
+    /// How many times a parameter has been set. Changes when anything does.
+    pub fn changes(&self) -> u64 {
+        self.changes.load(Ordering::Relaxed)
+    }
+
+    /// The current value of `param`. Bypass switches read 1.0 when on and 0.0 when off, choices
+    /// the index of the option.
+    pub fn get(&self, param: Param) -> f32 {
+        match param {
+            Param::DistortionBypass => switch_value(&self.distortion.bypass),
+            Param::DistortionLevel => self.distortion.level.load(Ordering::Relaxed),
+            Param::DistortionDrive => self.distortion.distortion.load(Ordering::Relaxed),
+            Param::DistortionShape => self.distortion.shape.load(Ordering::Relaxed) as f32,
+            Param::DelayBypass => switch_value(&self.delay.bypass),
+            Param::DelayTime => self.delay.time.load(Ordering::Relaxed),
+            Param::DelayDecay => self.delay.decay.load(Ordering::Relaxed),
+            Param::DelayMode => self.delay.mode.load(Ordering::Relaxed) as f32,
+            Param::DelayFilter => self.delay.filter.load(Ordering::Relaxed) as f32,
         }
     }
+
+    /// The lowest and highest value `param` can take.
+    pub fn range(&self, param: Param) -> (f32, f32) {
+        let min_max = match param {
+            Param::DistortionBypass | Param::DelayBypass => return (0.0, 1.0),
+            Param::DistortionShape => return choice_range(Shape::NAMES),
+            Param::DelayMode => return choice_range(DelayMode::NAMES),
+            Param::DelayFilter => return choice_range(DelayFilter::NAMES),
+            Param::DistortionLevel => &self.distortion.level_min_max,
+            Param::DistortionDrive => &self.distortion.distortion_min_max,
+            Param::DelayTime => &self.delay.time_min_max,
+            Param::DelayDecay => &self.delay.decay_min_max,
+        };
+        (
+            min_max[0].load(Ordering::Relaxed),
+            min_max[1].load(Ordering::Relaxed),
+        )
+    }
+
+    /// Sets `param`, the other way round from `get`.
+    pub fn set(&self, param: Param, value: f32) {
+        match param {
+            Param::DistortionBypass => self
+                .distortion
+                .bypass
+                .store(value >= 0.5, Ordering::Relaxed),
+            Param::DistortionLevel => self.distortion.level.store(value, Ordering::Relaxed),
+            Param::DistortionDrive => self.distortion.distortion.store(value, Ordering::Relaxed),
+            Param::DistortionShape => store_choice(&self.distortion.shape, value, Shape::NAMES),
+            Param::DelayBypass => self.delay.bypass.store(value >= 0.5, Ordering::Relaxed),
+            Param::DelayTime => self.delay.time.store(value, Ordering::Relaxed),
+            Param::DelayDecay => self.delay.decay.store(value, Ordering::Relaxed),
+            Param::DelayMode => store_choice(&self.delay.mode, value, DelayMode::NAMES),
+            Param::DelayFilter => store_choice(&self.delay.filter, value, DelayFilter::NAMES),
+        }
+        self.changes.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn switch_value(switch: &AtomicBool) -> f32 {
+    if switch.load(Ordering::Relaxed) {
+        1.0
+    } else {
+        0.0
+    }
 }
 
-pub enum DistortionEnum {
-    Bypass(AtomicBool),
-    Level(AtomicF32),
-    Distortion(AtomicF32),
-    LevelMinMax { min: AtomicF32, max: AtomicF32 },
-    DistortionMinMax { min: AtomicF32, max: AtomicF32 },
+fn choice_range(options: &[&str]) -> (f32, f32) {
+    (0.0, options.len().saturating_sub(1) as f32)
+}
+
+/// Stores the index of the option nearest `value`.
+fn store_choice(choice: &AtomicUsize, value: f32, options: &[&str]) {
+    let index = value.round().max(0.0) as usize;
+    choice.store(index.min(options.len() - 1), Ordering::Relaxed);
+}
+
+impl Default for EffectParams {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub struct DistortionParams {
@@ -37,6 +190,8 @@ pub struct DistortionParams {
     pub level_min_max: Vec<AtomicF32>,
     pub distortion: AtomicF32,
     pub distortion_min_max: Vec<AtomicF32>,
+    /// Index into `Shape::NAMES`.
+    pub shape: AtomicUsize,
 }
 
 impl DistortionParams {
@@ -47,18 +202,14 @@ impl DistortionParams {
             level_min_max: vec![AtomicF32::new(0.0), AtomicF32::new(1.0)],
             distortion: AtomicF32::new(0.5),
             distortion_min_max: vec![AtomicF32::new(0.0), AtomicF32::new(1.0)],
+            shape: AtomicUsize::new(0),
         }
     }
+}
 
-    pub fn change_level(&self, value: f32) {
-        self.level.store(
-            self.level.load(Ordering::Relaxed) + value * 0.1,
-            Ordering::Relaxed,
-        );
-    }
-
-    pub fn change_distortion(&self, value: f32) {
-        self.distortion.store(value, Ordering::Relaxed);
+impl Default for DistortionParams {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -68,6 +219,10 @@ pub struct DelayParams {
     pub time_min_max: Vec<AtomicF32>,
     pub decay: AtomicF32,
     pub decay_min_max: Vec<AtomicF32>,
+    /// Index into `DelayMode::NAMES`.
+    pub mode: AtomicUsize,
+    /// Index into `DelayFilter::NAMES`.
+    pub filter: AtomicUsize,
 }
 
 impl DelayParams {
@@ -78,6 +233,71 @@ impl DelayParams {
             time_min_max: vec![AtomicF32::new(0.0), AtomicF32::new(4.0)],
             decay: AtomicF32::new(0.8),
             decay_min_max: vec![AtomicF32::new(0.0), AtomicF32::new(1.0)],
+            mode: AtomicUsize::new(0),
+            filter: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// The curve the distortion drives the signal into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    /// Rounds the peaks off more and more the harder it is driven.
+    Soft,
+    /// Cuts the peaks off flat at full scale.
+    Hard,
+    /// Folds the peaks back down from full scale, for brighter overtones.
+    Fold,
+}
+
+impl Shape {
+    pub const NAMES: &'static [&'static str] = &["soft", "hard", "fold"];
+
+    pub fn from_index(index: usize) -> Self {
+        match index {
+            1 => Shape::Hard,
+            2 => Shape::Fold,
+            _ => Shape::Soft,
+        }
+    }
+}
+
+/// Where the echoes of a stereo signal come back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DelayMode {
+    /// Each channel echoes on its own side.
+    Normal,
+    /// Every repeat moves over to the next channel.
+    PingPong,
+}
+
+impl DelayMode {
+    pub const NAMES: &'static [&'static str] = &["normal", "ping-pong"];
+
+    pub fn from_index(index: usize) -> Self {
+        match index {
+            1 => DelayMode::PingPong,
+            _ => DelayMode::Normal,
+        }
+    }
+}
+
+/// The filter each repeat of the delay goes through, so the echoes get darker or thinner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DelayFilter {
+    Off,
+    LowPass,
+    HighPass,
+}
+
+impl DelayFilter {
+    pub const NAMES: &'static [&'static str] = &["off", "low-pass", "high-pass"];
+
+    pub fn from_index(index: usize) -> Self {
+        match index {
+            1 => DelayFilter::LowPass,
+            2 => DelayFilter::HighPass,
+            _ => DelayFilter::Off,
         }
     }
 }
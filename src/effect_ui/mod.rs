@@ -1,4 +1,5 @@
 pub mod delay_ui;
 pub mod distortion_ui;
+pub mod panel;
 pub mod param_widget;
 pub mod reverb_ui;
@@ -0,0 +1,145 @@
+// Lays out the controls of an effect tab in a grid that wraps to the space there is. When the
+// controls don't all fit at full size they shrink to a line of text each.
+
+use crate::app::App;
+use crate::effect_params::Param;
+use crate::effect_ui::param_widget::ParamWidget;
+use crate::mouse::KnobArea;
+use ratatui::{
+    Frame,
+    layout::{Margin, Rect},
+};
+
+/// The space a full size control takes.
+const CELL_WIDTH: u16 = 16;
+const CELL_HEIGHT: u16 = 12;
+/// The width of a control drawn as a line of text.
+const COMPACT_WIDTH: u16 = 24;
+
+/// Draws the controls for `params` inside the bordered `area`, with the one at `selected`
+/// highlighted, and records where each went for the mouse.
+pub fn draw_params(
+    frame: &mut Frame,
+    app: &mut App,
+    area: Rect,
+    params: &[Param],
+    selected: usize,
+) {
+    let (cells, compact) = layout(area.inner(Margin::new(2, 1)), params.len());
+
+    for ((index, &param), cell) in params.iter().enumerate().zip(cells) {
+        let (min, max) = app.effect_params.range(param);
+        let mut widget = ParamWidget::new(
+            param.label().to_string(),
+            app.effect_params.get(param),
+            min,
+            max,
+            param.unit(),
+        );
+        widget.selected = index == selected;
+        widget.tempo = app.tempo;
+        if widget.selected
+            && let Some(entry) = &app.entry
+        {
+            widget.entry = Some(entry.text.clone());
+            widget.entry_error = entry.error.is_some();
+        }
+
+        if compact {
+            widget.draw_compact(frame, cell, &app.theme);
+        } else {
+            widget.draw(frame, cell, &app.theme);
+        }
+        app.hit_areas.knobs.push(KnobArea {
+            area: cell,
+            index,
+            param,
+        });
+    }
+}
+
+/// Cells for `count` controls in `area`, and whether they had to be compact to fit.
+fn layout(area: Rect, count: usize) -> (Vec<Rect>, bool) {
+    let cells = grid(area, count, CELL_WIDTH, CELL_HEIGHT);
+    if cells.len() < count {
+        (grid(area, count, COMPACT_WIDTH, 1), true)
+    } else {
+        (cells, false)
+    }
+}
+
+/// Up to `count` cells of `width` by `height`, filling `area` row by row. Cells that would
+/// reach past the bottom are left out.
+fn grid(area: Rect, count: usize, width: u16, height: u16) -> Vec<Rect> {
+    let width = width.min(area.width);
+    let columns = (area.width / width.max(1)).max(1) as usize;
+    (0..count)
+        .map(|index| {
+            let column = (index % columns) as u16;
+            let row = (index / columns) as u16;
+            Rect::new(
+                area.x + column * width,
+                area.y.saturating_add(row.saturating_mul(height)),
+                width,
+                height,
+            )
+        })
+        .take_while(|cell| cell.bottom() <= area.bottom())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_size_controls_fill_a_row() {
+        let (cells, compact) = layout(Rect::new(2, 1, 48, 12), 3);
+        assert!(!compact);
+        assert_eq!(
+            cells,
+            [
+                Rect::new(2, 1, 16, 12),
+                Rect::new(18, 1, 16, 12),
+                Rect::new(34, 1, 16, 12)
+            ]
+        );
+    }
+
+    #[test]
+    fn full_size_controls_wrap_onto_more_rows() {
+        let (cells, compact) = layout(Rect::new(0, 0, 32, 24), 3);
+        assert!(!compact);
+        assert_eq!(cells[1], Rect::new(16, 0, 16, 12));
+        assert_eq!(cells[2], Rect::new(0, 12, 16, 12));
+    }
+
+    #[test]
+    fn controls_go_compact_when_they_dont_fit() {
+        // A column short of three full size controls.
+        let (cells, compact) = layout(Rect::new(0, 0, 47, 12), 3);
+        assert!(compact);
+        assert_eq!(
+            cells,
+            [
+                Rect::new(0, 0, 24, 1),
+                Rect::new(0, 1, 24, 1),
+                Rect::new(0, 2, 24, 1)
+            ]
+        );
+
+        // A row short.
+        let (cells, compact) = layout(Rect::new(0, 0, 48, 11), 3);
+        assert!(compact);
+        assert_eq!(cells[1], Rect::new(24, 0, 24, 1));
+        assert_eq!(cells[2], Rect::new(0, 1, 24, 1));
+    }
+
+    #[test]
+    fn controls_that_dont_fit_at_all_are_left_out() {
+        let (cells, compact) = layout(Rect::new(0, 0, 10, 2), 3);
+        assert!(compact);
+        assert_eq!(cells, [Rect::new(0, 0, 10, 1), Rect::new(0, 1, 10, 1)]);
+        assert!(layout(Rect::new(0, 0, 0, 0), 3).0.is_empty());
+    }
+}
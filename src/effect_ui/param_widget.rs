@@ -1,20 +1,48 @@
-use crate::app::{App, ParamSelection};
+// The controls the effect tabs are made of. Every control has a name above it and the value below
+// it, and is drawn in the way that suits what its parameter is: a fader for a gain or a time, a
+// switch for something on or off and a list for a choice between a few options.
+
+use crate::theme::Theme;
+use crate::units::{DEFAULT_TEMPO, Unit};
 use ratatui::{
     Frame,
     layout::{
         Constraint::{Length, Min},
         Layout, Rect,
     },
-    style::{Color, Modifier, Style},
+    style::{Color, Style},
     symbols,
-    text::{self, Span},
+    text::{Line as TextLine, Span},
     widgets::{
-        Axis, BarChart, Block, Cell, Chart, Dataset, Gauge, LineGauge, List, ListItem, Paragraph,
-        Row, Sparkline, Table, Tabs, Wrap,
-        block::title,
-        canvas::{self, Canvas, Circle, Line, Map, MapResolution, Rectangle},
+        Paragraph,
+        canvas::{Canvas, Circle, Line},
     },
 };
+use std::f32::consts::PI;
+
+/// How a parameter is drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Control {
+    Knob,
+    HorizontalFader,
+    VerticalFader,
+    Switch,
+    /// A list of the options with the chosen one highlighted.
+    Selector(&'static [&'static str]),
+}
+
+impl Control {
+    /// The control that suits values of `unit`.
+    pub fn for_unit(unit: Unit) -> Self {
+        match unit {
+            Unit::Gain => Control::VerticalFader,
+            Unit::Seconds => Control::HorizontalFader,
+            Unit::Percent => Control::Knob,
+            Unit::Switch => Control::Switch,
+            Unit::Choice(options) => Control::Selector(options),
+        }
+    }
+}
 
 pub struct ParamWidget {
     min: f32,
@@ -24,15 +52,28 @@ pub struct ParamWidget {
     line: Line,
     pub selected: bool,
     name: String,
+    unit: Unit,
+    pub control: Control,
+    /// Beats per minute, for showing times as note lengths.
+    pub tempo: f32,
+    /// Text being typed in for the value, shown instead of it.
+    pub entry: Option<String>,
+    /// Whether the typed text couldn't be used.
+    pub entry_error: bool,
 }
 
 impl ParamWidget {
-    pub fn new(name: String, value: f32, min: f32, max: f32) -> Self {
+    pub fn new(name: String, value: f32, min: f32, max: f32, unit: Unit) -> Self {
         ParamWidget {
             min,
             max,
             value,
             name,
+            unit,
+            control: Control::for_unit(unit),
+            tempo: DEFAULT_TEMPO,
+            entry: None,
+            entry_error: false,
             knob: Circle {
                 x: 0.0,
                 y: 0.0,
@@ -50,13 +91,58 @@ impl ParamWidget {
         }
     }
 
-    pub fn draw_knob(&mut self, frame: &mut Frame, app: &mut App, area: Rect) {
+    /// Draws the name, the control and the value, one above the other.
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect, theme: &Theme) {
+        let chunks =
+            Layout::vertical([Length(2), Length(1), Length(8), Length(1), Min(0)]).split(area);
+
+        frame.render_widget(self.title(theme).into_centered_line(), chunks[1]);
+        frame.render_widget(self.value_text(theme).into_centered_line(), chunks[3]);
+        match self.control {
+            Control::Knob => self.draw_knob(frame, chunks[2], theme),
+            Control::HorizontalFader => self.draw_horizontal_fader(frame, chunks[2], theme),
+            Control::VerticalFader => self.draw_vertical_fader(frame, chunks[2], theme),
+            Control::Switch => self.draw_switch(frame, chunks[2], theme),
+            Control::Selector(options) => self.draw_selector(frame, chunks[2], options, theme),
+        }
+    }
+
+    /// Draws the name and the value on one line, for when there's no room for the control.
+    pub fn draw_compact(&self, frame: &mut Frame, area: Rect, theme: &Theme) {
+        let chunks = Layout::horizontal([Min(0), Length(12), Length(2)]).split(area);
+        frame.render_widget(self.title(theme).into_left_aligned_line(), chunks[0]);
+        frame.render_widget(self.value_text(theme).into_right_aligned_line(), chunks[1]);
+    }
+
+    fn title(&self, theme: &Theme) -> Span<'_> {
+        if self.selected {
+            Span::styled(&self.name, theme.selected)
+        } else {
+            Span::styled(&self.name, Style::default())
+        }
+    }
+
+    /// The value, or the text being typed in for it.
+    fn value_text(&self, theme: &Theme) -> Span<'_> {
+        match &self.entry {
+            Some(entry) => {
+                let style = if self.entry_error {
+                    theme.error
+                } else {
+                    theme.accent
+                };
+                Span::styled(format!("{entry}_"), style)
+            }
+            None => Span::from(self.unit.format(self.value, self.tempo)),
+        }
+    }
+
+    fn draw_knob(&mut self, frame: &mut Frame, area: Rect, theme: &Theme) {
         let angle = self.get_rad(self.value);
         self.line.x2 = 4.0 * f32::cos(angle) as f64;
         self.line.y2 = 4.0 * f32::sin(angle) as f64;
-
-        let chunks =
-            Layout::vertical([Length(2), Length(1), Length(8), Length(1), Min(0)]).split(area);
+        self.knob.color = theme.control_color();
+        self.line.color = theme.control_color();
 
         let knob = Canvas::default()
             //.block(Block::bordered().title("knob"))
@@ -67,28 +153,79 @@ impl ParamWidget {
             })
             .x_bounds([-6.0, 6.0])
             .y_bounds([-6.0, 6.0]);
+        frame.render_widget(knob, area);
+    }
 
-        let mut title = Span::styled(&self.name, Style::default()).into_centered_line();
-        if self.selected {
-            title = Span::styled(
-                &self.name,
-                Style::default().add_modifier(Modifier::REVERSED),
-            )
-            .into_centered_line();
-        }
+    /// A track filled from the left up to the value.
+    fn draw_horizontal_fader(&self, frame: &mut Frame, area: Rect, theme: &Theme) {
+        let width = area.width.saturating_sub(2) as usize;
+        let filled = (self.normalized() * width as f32).round() as usize;
+        let track = format!("{}{}", "━".repeat(filled), "─".repeat(width - filled));
+        let row = Rect {
+            y: area.y + area.height / 2,
+            height: 1,
+            ..area
+        };
+        frame.render_widget(TextLine::styled(track, theme.control).centered(), row);
+    }
+
+    /// A track filled from the bottom up to the value.
+    fn draw_vertical_fader(&self, frame: &mut Frame, area: Rect, theme: &Theme) {
+        let height = area.height as usize;
+        let filled = (self.normalized() * height as f32).round() as usize;
+        let rows: Vec<TextLine> = (0..height)
+            .map(|row| {
+                let symbol = if height - row <= filled { "┃" } else { "│" };
+                TextLine::styled(symbol, theme.control).centered()
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(rows), area);
+    }
+
+    fn draw_switch(&self, frame: &mut Frame, area: Rect, theme: &Theme) {
+        let (text, style) = if self.value >= 0.5 {
+            (" ON ", theme.switch_on)
+        } else {
+            (" OFF ", theme.muted)
+        };
+        let row = Rect {
+            y: area.y + area.height / 2,
+            height: 1,
+            ..area
+        };
+        frame.render_widget(Span::styled(text, style).into_centered_line(), row);
+    }
+
+    /// The options one per line, the chosen one highlighted.
+    fn draw_selector(&self, frame: &mut Frame, area: Rect, options: &[&str], theme: &Theme) {
+        let chosen = self.value.round().max(0.0) as usize;
+        let rows: Vec<TextLine> = options
+            .iter()
+            .enumerate()
+            .map(|(index, option)| {
+                let style = if index == chosen {
+                    theme.selected
+                } else {
+                    Style::default()
+                };
+                Span::styled(*option, style).into_centered_line()
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(rows), area);
+    }
 
-        let value = Span::from(self.value.to_string()).into_centered_line();
-        frame.render_widget(title, chunks[1]);
-        frame.render_widget(knob, chunks[2]);
-        frame.render_widget(value, chunks[3]);
+    /// How far along its range the value is, from 0 to 1.
+    fn normalized(&self) -> f32 {
+        ((self.value - self.min) / (self.max - self.min)).clamp(0.0, 1.0)
     }
 
     fn get_rad(&self, value: f32) -> f32 {
+        // The knob sweeps 270 degrees clockwise, starting at the bottom left.
         let normalized_value = (value - self.min) / (self.max - self.min);
-        if normalized_value < 0.8333333333333333 {
-            -4.7123889803847 * normalized_value + 3.9269908169872
+        if normalized_value < 5.0 / 6.0 {
+            -1.5 * PI * normalized_value + 1.25 * PI
         } else {
-            -4.7123889803847 * normalized_value + 10.2101761241668
+            -1.5 * PI * normalized_value + 3.25 * PI
         }
     }
 }
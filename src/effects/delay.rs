@@ -1,37 +1,129 @@
-// TODO
-use crate::effect_params::EffectParams;
-use ringbuf::{
-    HeapRb, SharedRb,
-    storage::Heap,
-    traits::{Consumer, Producer, Split},
-    wrap::caching::Caching,
-};
-use std::sync::{Arc, Mutex, atomic::Ordering};
+// A feedback delay: the output is written back into the delay line, so each echo comes back
+// again one delay time later, quieter by the decay. In ping-pong mode each channel's output is
+// written into the next channel's line instead, so the echoes move across the channels. The
+// filter sits in the feedback path, so every repeat is darker or thinner than the one before.
+
+use super::smoother::Smoother;
+use crate::effect_params::{DelayFilter, DelayMode, EffectParams, Param};
+use std::f32::consts::TAU;
+use std::sync::{Arc, atomic::Ordering};
+
+/// How long the echo takes to follow a new delay time. Slower than other parameters, because
+/// moving the read position is heard as a brief change of pitch.
+const TIME_RAMP_SECONDS: f32 = 0.2;
+/// Where the low-pass filter starts rolling off the echoes.
+const LOW_PASS_HZ: f32 = 2_000.0;
+/// Where the high-pass filter starts rolling off the echoes.
+const HIGH_PASS_HZ: f32 = 400.0;
 
 pub struct Delay {
     params: Arc<EffectParams>,
-    // buffer: HeapRb::<f32>,
-    prod: Caching<Arc<SharedRb<Heap<f32>>>, true, false>,
-    cons: Caching<Arc<SharedRb<Heap<f32>>>, false, true>,
+    channels: usize,
+    sample_rate: f32,
+    /// Circular delay line of interleaved frames, long enough for the longest delay time.
+    buffer: Vec<f32>,
+    /// Where the next sample is written.
+    position: usize,
+    /// The delay in frames, which may fall between two of them.
+    frames: Smoother,
+    max_frames: f32,
+    decay: Smoother,
+    /// The one-pole low-pass each channel's echo goes through. The high-pass is what it takes out.
+    filter_state: Vec<f32>,
+    low_pass: f32,
+    high_pass: f32,
 }
 
 impl Delay {
-    pub fn new(sample_rate: f32, params: Arc<EffectParams>) -> Self {
-        let buffer = HeapRb::<f32>::new((sample_rate * 2.0 * 2.0) as usize);
-        let (mut prod, mut cons) = buffer.split();
-
-        for _ in 0..(sample_rate * 2.0) as usize {
-            prod.try_push(0.0).unwrap();
+    /// `ramp` is how many samples a change of decay or bypass takes.
+    pub fn new(sample_rate: u32, channels: usize, params: Arc<EffectParams>, ramp: usize) -> Self {
+        let sample_rate = sample_rate as f32;
+        let (_, max_time) = params.range(Param::DelayTime);
+        let max_frames = (max_time * sample_rate).ceil().max(1.0);
+        let frames = frames_for(&params, sample_rate, max_frames);
+        let decay = decay_for(&params);
+        Self {
+            params,
+            channels,
+            sample_rate,
+            // One frame more than the longest delay, to interpolate past it.
+            buffer: vec![0.0; (max_frames as usize + 2) * channels],
+            position: 0,
+            frames: Smoother::new(frames, (TIME_RAMP_SECONDS * sample_rate) as usize),
+            max_frames,
+            decay: Smoother::new(decay, ramp),
+            filter_state: vec![0.0; channels],
+            low_pass: one_pole(LOW_PASS_HZ, sample_rate),
+            high_pass: one_pole(HIGH_PASS_HZ, sample_rate),
         }
+    }
 
-        Self { params, prod, cons }
+    /// Adds the decaying echo to `block` in place. The delay time, decay, mode and filter are
+    /// read once for the whole block.
+    pub fn process_block(&mut self, block: &mut [f32]) {
+        self.decay.set_target(decay_for(&self.params));
+        self.frames
+            .set_target(frames_for(&self.params, self.sample_rate, self.max_frames));
+        let ping_pong = DelayMode::from_index(self.params.delay.mode.load(Ordering::Relaxed))
+            == DelayMode::PingPong;
+        let filter = DelayFilter::from_index(self.params.delay.filter.load(Ordering::Relaxed));
+        let coefficient = match filter {
+            DelayFilter::Off => 0.0,
+            DelayFilter::LowPass => self.low_pass,
+            DelayFilter::HighPass => self.high_pass,
+        };
+        let len = self.buffer.len();
+        for frame in block.chunks_exact_mut(self.channels) {
+            // Read between the two frames around the delay time, so it can change smoothly.
+            let delay = self.frames.step();
+            let whole = delay as usize;
+            let fraction = delay - whole as f32;
+            let newer = (self.position + len - whole * self.channels) % len;
+            let older = (newer + len - self.channels) % len;
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let decay = self.decay.step();
+                let newer = self.buffer[newer + channel];
+                let delayed = newer + (self.buffer[older + channel] - newer) * fraction;
+                let state = &mut self.filter_state[channel];
+                *state += (delayed - *state) * coefficient;
+                let filtered = match filter {
+                    DelayFilter::Off => delayed,
+                    DelayFilter::LowPass => *state,
+                    DelayFilter::HighPass => delayed - *state,
+                };
+                *sample += filtered * decay;
+            }
+            // Each channel's line takes in the output of the channel before it in ping-pong
+            // mode, and its own otherwise.
+            for channel in 0..self.channels {
+                let source = if ping_pong {
+                    (channel + self.channels - 1) % self.channels
+                } else {
+                    channel
+                };
+                self.buffer[self.position + channel] = frame[source];
+            }
+            self.position = (self.position + self.channels) % len;
+        }
     }
+}
+
+/// The coefficient of a one-pole low-pass filter rolling off from `hz`.
+fn one_pole(hz: f32, sample_rate: f32) -> f32 {
+    1.0 - (-TAU * hz / sample_rate).exp()
+}
 
-    pub fn process(&mut self, sample: f32) -> f32 {
-        let delayed_sample =
-            self.cons.try_pop().unwrap() * self.params.delay.decay.load(Ordering::Relaxed) as f32;
-        self.prod.try_push(sample + delayed_sample).unwrap();
+/// The delay time in frames, at least one so the echo never reads the frame being written.
+fn frames_for(params: &EffectParams, sample_rate: f32, max_frames: f32) -> f32 {
+    (params.delay.time.load(Ordering::Relaxed) * sample_rate).clamp(1.0, max_frames)
+}
 
-        return sample + delayed_sample;
+/// Bypassed, the line keeps taking in the dry signal so switching back on doesn't replay stale
+/// echoes.
+fn decay_for(params: &EffectParams) -> f32 {
+    if params.delay.bypass.load(Ordering::Relaxed) {
+        0.0
+    } else {
+        params.delay.decay.load(Ordering::Relaxed)
     }
 }
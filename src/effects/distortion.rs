@@ -1,17 +1,86 @@
-// TODO
-use crate::effect_params::EffectParams;
-use std::sync::{Arc, Mutex, atomic::Ordering};
+// Waveshaping distortion: the signal is driven into a clipper of the chosen shape, then scaled by
+// the level.
+
+use super::smoother::Smoother;
+use crate::effect_params::{EffectParams, Shape};
+use std::sync::{Arc, atomic::Ordering};
+
+/// The gain in front of the clipper at full drive.
+const MAX_DRIVE_GAIN: f32 = 20.0;
 
 pub struct Distortion {
     params: Arc<EffectParams>,
+    level: Smoother,
+    /// The gain the signal is pushed into the clipper with.
+    gain: Smoother,
+    /// How much of the distorted signal is heard, ramping to 0 when bypassed.
+    mix: Smoother,
 }
 
 impl Distortion {
-    pub fn new(params: Arc<EffectParams>) -> Self {
-        Self { params }
+    /// `ramp` is how many samples a change of level, drive or bypass takes.
+    pub fn new(params: Arc<EffectParams>, ramp: usize) -> Self {
+        let level = params.distortion.level.load(Ordering::Relaxed);
+        let gain = gain_for(&params);
+        let mix = mix_for(&params);
+        Self {
+            params,
+            level: Smoother::new(level, ramp),
+            gain: Smoother::new(gain, ramp),
+            mix: Smoother::new(mix, ramp),
+        }
+    }
+
+    /// Distorts `block` in place. The level, drive and shape are read once for the whole block.
+    pub fn process_block(&mut self, block: &mut [f32]) {
+        let shape = Shape::from_index(self.params.distortion.shape.load(Ordering::Relaxed));
+        self.level
+            .set_target(self.params.distortion.level.load(Ordering::Relaxed));
+        self.gain.set_target(gain_for(&self.params));
+        self.mix.set_target(mix_for(&self.params));
+
+        if self.mix.is_settled() && self.level.is_settled() && self.gain.is_settled() {
+            if self.mix.value() == 0.0 {
+                return;
+            }
+            let level = self.level.value();
+            let gain = self.gain.value();
+            for sample in block {
+                *sample = clip(*sample, gain, shape) * level;
+            }
+            return;
+        }
+
+        for sample in block {
+            let level = self.level.step();
+            let gain = self.gain.step();
+            let mix = self.mix.step();
+            let distorted = clip(*sample, gain, shape) * level;
+            *sample += (distorted - *sample) * mix;
+        }
     }
+}
+
+/// Clips `sample` pushed in with `gain`: the more gain, the harder the clipping.
+#[inline]
+fn clip(sample: f32, gain: f32, shape: Shape) -> f32 {
+    let driven = sample * gain;
+    match shape {
+        Shape::Soft => driven.tanh(),
+        Shape::Hard => driven.clamp(-1.0, 1.0),
+        // A triangle wave of the input: past full scale the signal turns back towards zero.
+        Shape::Fold => 1.0 - ((driven + 1.0).rem_euclid(4.0) - 2.0).abs(),
+    }
+}
+
+fn gain_for(params: &EffectParams) -> f32 {
+    1.0 + params.distortion.distortion.load(Ordering::Relaxed) * (MAX_DRIVE_GAIN - 1.0)
+}
 
-    pub fn process(&self, sample: f32) -> f32 {
-        return sample.powf(3.0) * self.params.distortion.level.load(Ordering::Relaxed) as f32;
+fn mix_for(params: &EffectParams) -> f32 {
+    if params.distortion.bypass.load(Ordering::Relaxed) {
+        0.0
+    } else {
+        1.0
     }
 }
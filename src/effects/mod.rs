@@ -1,3 +1,169 @@
-pub mod distortion;
 pub mod delay;
-pub mod reverb;
\ No newline at end of file
+pub mod distortion;
+pub mod reverb;
+pub mod smoother;
+
+use crate::effect_params::EffectParams;
+use delay::Delay;
+use distortion::Distortion;
+use std::sync::Arc;
+
+/// The effects in the order the signal goes through them. Processing never allocates, locks or
+/// panics, so it is safe to run in a stream callback.
+pub struct EffectChain {
+    distortion: Distortion,
+    delay: Delay,
+}
+
+impl EffectChain {
+    /// Sets up the effects for interleaved audio of `channels` channels at `sample_rate`.
+    pub fn new(sample_rate: u32, channels: usize, params: Arc<EffectParams>) -> Self {
+        let ramp = (smoother::RAMP_SECONDS * sample_rate as f32) as usize * channels;
+        Self {
+            distortion: Distortion::new(Arc::clone(&params), ramp),
+            delay: Delay::new(sample_rate, channels, params, ramp),
+        }
+    }
+
+    /// Runs a block of interleaved frames through every effect in place. Each effect reads its
+    /// parameters once per block, so longer blocks are cheaper per sample.
+    pub fn process_block(&mut self, block: &mut [f32]) {
+        self.distortion.process_block(block);
+        self.delay.process_block(block);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_no_alloc::assert_no_alloc;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn chain_runs_without_allocating() {
+        let params = Arc::new(EffectParams::new());
+        let mut chain = EffectChain::new(48_000, 2, params);
+        let input: Vec<f32> = (0..1024).map(|i| (i as f32 * 0.01).sin()).collect();
+        let mut buffer = vec![0.0; input.len()];
+
+        // Long enough for the delay line to wrap around several times.
+        assert_no_alloc(|| {
+            for _ in 0..500 {
+                buffer.copy_from_slice(&input);
+                chain.process_block(&mut buffer);
+            }
+        });
+        assert!(buffer.iter().all(|sample| sample.is_finite()));
+    }
+
+    #[test]
+    fn block_size_doesnt_change_the_result() {
+        let params = Arc::new(EffectParams::new());
+        let input: Vec<f32> = (0..10_000).map(|i| (i as f32 * 0.003).sin()).collect();
+
+        // A tiny delay line, so the blocks wrap around it at different points.
+        let mut whole = input.clone();
+        let mut chain = EffectChain::new(500, 1, Arc::clone(&params));
+        chain.process_block(&mut whole);
+
+        let mut pieces = input.clone();
+        let mut chain = EffectChain::new(500, 1, params);
+        for block in pieces.chunks_mut(77) {
+            chain.process_block(block);
+        }
+        assert_eq!(whole, pieces);
+    }
+
+    #[test]
+    fn echo_comes_after_the_delay_time() {
+        let params = Arc::new(EffectParams::new());
+        params.delay.time.store(0.01, Ordering::Relaxed);
+        params.delay.decay.store(0.5, Ordering::Relaxed);
+        let mut delay = Delay::new(1_000, 2, params, 1);
+
+        // A click on the right channel only.
+        let mut block = vec![0.0; 60];
+        block[1] = 1.0;
+        delay.process_block(&mut block);
+        let echoes: Vec<usize> = (0..block.len()).filter(|&i| block[i] != 0.0).collect();
+        assert_eq!(echoes, [1, 21, 41]);
+        assert_eq!(block[21], 0.5);
+        assert_eq!(block[41], 0.25);
+    }
+
+    #[test]
+    fn ping_pong_echoes_cross_over() {
+        let params = Arc::new(EffectParams::new());
+        params.delay.time.store(0.01, Ordering::Relaxed);
+        params.delay.decay.store(0.5, Ordering::Relaxed);
+        params.delay.mode.store(1, Ordering::Relaxed);
+        let mut delay = Delay::new(1_000, 2, params, 1);
+
+        let mut block = vec![0.0; 80];
+        block[1] = 1.0;
+        delay.process_block(&mut block);
+        let echoes: Vec<usize> = (0..block.len()).filter(|&i| block[i] != 0.0).collect();
+        assert_eq!(echoes, [1, 20, 41, 60]);
+        assert_eq!([block[20], block[41], block[60]], [0.5, 0.25, 0.125]);
+    }
+
+    #[test]
+    fn filters_shape_the_echo() {
+        let echo = |filter: usize| {
+            let params = Arc::new(EffectParams::new());
+            params.delay.time.store(0.01, Ordering::Relaxed);
+            params.delay.decay.store(0.5, Ordering::Relaxed);
+            params.delay.filter.store(filter, Ordering::Relaxed);
+            let mut delay = Delay::new(48_000, 1, params, 1);
+            let mut block = vec![0.0; 960];
+            block[0] = 1.0;
+            delay.process_block(&mut block);
+            // The first echo and the sample after it.
+            [block[480], block[481]]
+        };
+        assert_eq!(echo(0), [0.5, 0.0]);
+        // Low-pass spreads the click out, high-pass takes its average back out after it.
+        let [peak, after] = echo(1);
+        assert!(peak < 0.5 && after > 0.0);
+        let [peak, after] = echo(2);
+        assert!(peak < 0.5 && after < 0.0);
+    }
+
+    #[test]
+    fn shapes_clip_differently() {
+        let params = Arc::new(EffectParams::new());
+        params.distortion.level.store(1.0, Ordering::Relaxed);
+        params.distortion.distortion.store(0.0, Ordering::Relaxed);
+        let distort = |shape: usize, sample: f32| {
+            params.distortion.shape.store(shape, Ordering::Relaxed);
+            let mut block = [sample, -sample];
+            Distortion::new(Arc::clone(&params), 1).process_block(&mut block);
+            assert_eq!(block[1], -block[0]);
+            block[0]
+        };
+        assert_eq!(distort(0, 2.0), 2.0_f32.tanh());
+        assert_eq!(distort(1, 2.0), 1.0);
+        assert_eq!(distort(1, 0.5), 0.5);
+        assert_eq!(distort(2, 1.5), 0.5);
+        assert_eq!(distort(2, 0.5), 0.5);
+    }
+
+    #[test]
+    fn drive_clips_harder() {
+        let params = Arc::new(EffectParams::new());
+        params.distortion.level.store(1.0, Ordering::Relaxed);
+        let distort = |drive: f32, sample: f32| {
+            params.distortion.distortion.store(drive, Ordering::Relaxed);
+            let mut block = [sample, -sample];
+            Distortion::new(Arc::clone(&params), 1).process_block(&mut block);
+            assert_eq!(block[1], -block[0]);
+            block[0]
+        };
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-6;
+        assert!(close(distort(0.0, 0.1), 0.1_f32.tanh()));
+        assert!(close(distort(1.0, 0.1), 2.0_f32.tanh()));
+        assert!(distort(0.5, 0.1) > distort(0.0, 0.1));
+        assert!(distort(0.5, 0.1) < distort(1.0, 0.1));
+        assert!(distort(1.0, 10.0) <= 1.0);
+    }
+}
@@ -0,0 +1,58 @@
+// Ramps a parameter towards a new value over a fixed time instead of jumping, so sudden changes
+// such as switching snapshots don't click.
+
+/// How long a ramp to a new value takes.
+pub const RAMP_SECONDS: f32 = 0.02;
+
+pub struct Smoother {
+    value: f32,
+    target: f32,
+    increment: f32,
+    remaining: usize,
+    length: usize,
+}
+
+impl Smoother {
+    /// Starts settled at `value`. Every ramp takes `length` calls to `step`.
+    pub fn new(value: f32, length: usize) -> Self {
+        Self {
+            value,
+            target: value,
+            increment: 0.0,
+            remaining: 0,
+            length: length.max(1),
+        }
+    }
+
+    /// Starts a new ramp from wherever the value is now if `target` changed.
+    pub fn set_target(&mut self, target: f32) {
+        if target != self.target {
+            self.target = target;
+            self.remaining = self.length;
+            self.increment = (target - self.value) / self.length as f32;
+        }
+    }
+
+    /// Advances the ramp by one sample and returns the value for it.
+    #[inline]
+    pub fn step(&mut self) -> f32 {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            self.value = if self.remaining == 0 {
+                self.target
+            } else {
+                self.value + self.increment
+            };
+        }
+        self.value
+    }
+
+    /// Whether the value has reached the target.
+    pub fn is_settled(&self) -> bool {
+        self.remaining == 0
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+}
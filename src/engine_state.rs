@@ -0,0 +1,122 @@
+use crate::pipeline::StreamSettings;
+use crate::stats::StreamStats;
+use std::sync::{
+    Mutex,
+    atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering},
+};
+
+/// What the audio streams are currently doing, as shown in the TUI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamStatus {
+    Starting,
+    Running,
+    /// Running, but on a default device because a requested one is missing.
+    Fallback,
+    /// The streams failed and are being rebuilt.
+    Reconnecting,
+}
+
+impl StreamStatus {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Running,
+            2 => Self::Fallback,
+            3 => Self::Reconnecting,
+            _ => Self::Starting,
+        }
+    }
+}
+
+/// What the streams run on once started, which may differ from the settings asked for.
+#[derive(Clone, Debug)]
+pub struct ActiveStreams {
+    pub host: String,
+    pub input_device: String,
+    pub output_device: String,
+    pub sample_rate: u32,
+    /// Frames per callback, if the streams asked for a fixed size.
+    pub buffer_size: Option<u32>,
+}
+
+/// State shared between the pipeline thread, the stream callbacks and the UI.
+pub struct EngineState {
+    status: AtomicU8,
+    stream_failed: AtomicBool,
+    /// Bumped by the output callback so the pipeline can notice streams that stall silently.
+    pub heartbeat: AtomicU64,
+    pub stats: StreamStats,
+    message: Mutex<String>,
+    settings: Mutex<Option<StreamSettings>>,
+    active: Mutex<Option<ActiveStreams>>,
+}
+
+impl EngineState {
+    pub fn new() -> Self {
+        Self {
+            status: AtomicU8::new(StreamStatus::Starting as u8),
+            stream_failed: AtomicBool::new(false),
+            heartbeat: AtomicU64::new(0),
+            stats: StreamStats::new(),
+            message: Mutex::new(String::new()),
+            settings: Mutex::new(None),
+            active: Mutex::new(None),
+        }
+    }
+
+    pub fn status(&self) -> StreamStatus {
+        StreamStatus::from_u8(self.status.load(Ordering::Relaxed))
+    }
+
+    pub fn set_status(&self, status: StreamStatus) {
+        self.status.store(status as u8, Ordering::Relaxed);
+    }
+
+    /// The last error or notice from the pipeline, empty if there is none.
+    pub fn message(&self) -> String {
+        self.message.lock().unwrap().clone()
+    }
+
+    pub fn set_message(&self, message: impl Into<String>) {
+        *self.message.lock().unwrap() = message.into();
+    }
+
+    /// The settings the pipeline is running with, once it has started.
+    pub fn settings(&self) -> Option<StreamSettings> {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn set_settings(&self, settings: StreamSettings) {
+        *self.settings.lock().unwrap() = Some(settings);
+    }
+
+    /// What the last streams to start run on.
+    pub fn active(&self) -> Option<ActiveStreams> {
+        self.active.lock().unwrap().clone()
+    }
+
+    pub fn set_active(&self, active: ActiveStreams) {
+        *self.active.lock().unwrap() = Some(active);
+    }
+
+    /// Called from the stream error callbacks. Errors that leave the stream unusable flag it for
+    /// a rebuild; glitches are only counted.
+    pub fn report_stream_error(&self, err: &cpal::StreamError) {
+        if matches!(err, cpal::StreamError::BufferUnderrun) {
+            self.stats.record_underrun();
+            return;
+        }
+        self.set_message(err.to_string());
+        self.stream_failed.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether a stream has failed since the last call.
+    pub fn take_stream_failure(&self) -> bool {
+        self.stream_failed.swap(false, Ordering::SeqCst)
+    }
+}
+
+impl Default for EngineState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
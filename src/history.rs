@@ -0,0 +1,192 @@
+// Undo and redo for parameter edits made in the UI.
+//
+// Every edit is a list of parameter changes undone or redone together, so loading a whole set of
+// values takes one step. Holding a key down makes a burst of tiny edits to one parameter; those
+// are folded into a single entry so undo jumps back to where the burst started. Switching
+// snapshots is always an entry of its own that also remembers which slot was active.
+
+use crate::effect_params::{EffectParams, Param};
+use std::time::{Duration, Instant};
+
+/// Edits to the same parameter closer together than this are folded into one.
+const COALESCE_WINDOW: Duration = Duration::from_millis(500);
+/// Oldest edits are forgotten beyond this many.
+const MAX_ENTRIES: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Change {
+    pub param: Param,
+    pub from: f32,
+    pub to: f32,
+}
+
+/// A switch from one snapshot slot to another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlotChange {
+    pub from: usize,
+    pub to: usize,
+}
+
+struct Entry {
+    changes: Vec<Change>,
+    /// Set for a snapshot switch.
+    slot: Option<SlotChange>,
+    at: Instant,
+    /// Whether later edits may be folded into this one.
+    open: bool,
+}
+
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Entry>,
+    redo: Vec<Entry>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records changes that have already been applied. Starting a new edit drops anything that
+    /// could have been redone.
+    pub fn record(&mut self, changes: Vec<Change>) {
+        self.record_at(changes, Instant::now());
+    }
+
+    fn record_at(&mut self, changes: Vec<Change>, at: Instant) {
+        if changes.is_empty() {
+            return;
+        }
+        self.redo.clear();
+
+        // A lone change to the parameter the last lone change was to, shortly after it, only
+        // moves that entry's end point. Switches flip back and forth, so they never fold.
+        if let ([change], Some(last)) = (changes.as_slice(), self.undo.last_mut())
+            && let [last_change] = last.changes.as_mut_slice()
+            && last.open
+            && !change.param.is_switch()
+            && last_change.param == change.param
+            && at.duration_since(last.at) < COALESCE_WINDOW
+        {
+            last_change.to = change.to;
+            last.at = at;
+            return;
+        }
+
+        self.push(Entry {
+            changes,
+            slot: None,
+            at,
+            open: true,
+        });
+    }
+
+    /// Records a snapshot switch that has already been applied. It is never folded into other
+    /// edits, even when it changes nothing, so one undo always goes back one switch.
+    pub fn record_switch(&mut self, changes: Vec<Change>, slot: SlotChange) {
+        self.redo.clear();
+        self.push(Entry {
+            changes,
+            slot: Some(slot),
+            at: Instant::now(),
+            open: false,
+        });
+    }
+
+    fn push(&mut self, entry: Entry) {
+        self.undo.push(entry);
+        if self.undo.len() > MAX_ENTRIES {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Puts back the values from before the latest edit. Returns `None` if there was nothing to
+    /// undo, or else the snapshot slot to make active again if the edit was a switch.
+    pub fn undo(&mut self, params: &EffectParams) -> Option<Option<usize>> {
+        let mut entry = self.undo.pop()?;
+        for change in entry.changes.iter().rev() {
+            params.set(change.param, change.from);
+        }
+        // An edit that was undone and redone is never continued by the next one.
+        entry.open = false;
+        let slot = entry.slot.map(|slot| slot.from);
+        self.redo.push(entry);
+        Some(slot)
+    }
+
+    /// Applies the latest undone edit again. Returns the same as `undo`.
+    pub fn redo(&mut self, params: &EffectParams) -> Option<Option<usize>> {
+        let entry = self.redo.pop()?;
+        for change in &entry.changes {
+            params.set(change.param, change.to);
+        }
+        let slot = entry.slot.map(|slot| slot.to);
+        self.undo.push(entry);
+        Some(slot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(param: Param, from: f32, to: f32) -> Vec<Change> {
+        vec![Change { param, from, to }]
+    }
+
+    /// Undoes everything, returning how many steps it took.
+    fn undo_all(history: &mut History, params: &EffectParams) -> usize {
+        std::iter::from_fn(|| history.undo(params)).count()
+    }
+
+    #[test]
+    fn folds_a_burst_of_edits_to_one_parameter() {
+        let params = EffectParams::new();
+        let mut history = History::new();
+        let start = Instant::now();
+        let step = Duration::from_millis(400);
+        // Each edit within the window of the one before, though the burst lasts longer.
+        for i in 0..4 {
+            let from = 0.1 * i as f32;
+            history.record_at(
+                change(Param::DelayDecay, from, from + 0.1),
+                start + step * i,
+            );
+        }
+        params.set(Param::DelayDecay, 0.4);
+        assert_eq!(undo_all(&mut history, &params), 1);
+        assert_eq!(params.get(Param::DelayDecay), 0.0);
+    }
+
+    #[test]
+    fn splits_after_the_window_or_another_edit() {
+        let params = EffectParams::new();
+        let mut history = History::new();
+        let start = Instant::now();
+        history.record_at(change(Param::DelayDecay, 0.0, 0.1), start);
+        // Too late to fold in.
+        let later = start + COALESCE_WINDOW;
+        history.record_at(change(Param::DelayDecay, 0.1, 0.2), later);
+        // Another parameter, then back to the first.
+        history.record_at(change(Param::DelayTime, 0.5, 0.6), later);
+        history.record_at(change(Param::DelayDecay, 0.2, 0.3), later);
+        // Switches never fold, nor does an edit into one.
+        history.record_at(change(Param::DelayBypass, 0.0, 1.0), later);
+        history.record_at(change(Param::DelayBypass, 1.0, 0.0), later);
+        assert_eq!(undo_all(&mut history, &params), 6);
+        assert_eq!(params.get(Param::DelayDecay), 0.0);
+        assert_eq!(params.get(Param::DelayTime), 0.5);
+    }
+
+    #[test]
+    fn undone_edits_are_not_continued() {
+        let params = EffectParams::new();
+        let mut history = History::new();
+        let now = Instant::now();
+        history.record_at(change(Param::DelayDecay, 0.0, 0.1), now);
+        history.undo(&params);
+        history.redo(&params);
+        history.record_at(change(Param::DelayDecay, 0.1, 0.2), now);
+        assert_eq!(undo_all(&mut history, &params), 2);
+    }
+}
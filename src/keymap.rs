@@ -0,0 +1,384 @@
+// Key bindings: which key does what on which screen.
+//
+// Keys map to named actions. Each action belongs to a context, the screen it works on, or is
+// global. The defaults below can be replaced per action from the `[keys]` table of the config
+// file, e.g. `undo = ["u", "ctrl+z"]`. A key bound to two actions that can be active at the same
+// time is an error.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Where an action works.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Context {
+    /// Everywhere, unless the screen binds the key to something else.
+    Global,
+    Effects,
+    Settings,
+    Log,
+}
+
+macro_rules! actions {
+    ($($variant:ident => $name:literal, $context:ident, $description:literal;)*) => {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum Action {
+            $($variant,)*
+        }
+
+        impl Action {
+            pub const ALL: &[Action] = &[$(Action::$variant,)*];
+
+            /// The name used in the config file.
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Action::$variant => $name,)*
+                }
+            }
+
+            pub fn context(self) -> Context {
+                match self {
+                    $(Action::$variant => Context::$context,)*
+                }
+            }
+
+            pub fn description(self) -> &'static str {
+                match self {
+                    $(Action::$variant => $description,)*
+                }
+            }
+        }
+    };
+}
+
+actions! {
+    Quit => "quit", Global, "Quit";
+    NextTab => "next_tab", Global, "Go to the next tab";
+    PreviousTab => "previous_tab", Global, "Go to the previous tab";
+    Help => "help", Global, "Show the keys";
+    Close => "close", Global, "Close the keys";
+    CommandPalette => "command_palette", Global, "Search for a command to run";
+    NextParam => "next_param", Effects, "Select the next parameter";
+    PreviousParam => "previous_param", Effects, "Select the previous parameter";
+    Increase => "increase", Effects, "Turn the parameter up";
+    Decrease => "decrease", Effects, "Turn the parameter down";
+    IncreaseCoarse => "increase_coarse", Effects, "Turn the parameter up in big steps";
+    DecreaseCoarse => "decrease_coarse", Effects, "Turn the parameter down in big steps";
+    IncreaseFine => "increase_fine", Effects, "Turn the parameter up in small steps";
+    DecreaseFine => "decrease_fine", Effects, "Turn the parameter down in small steps";
+    EnterValue => "enter_value", Effects, "Type in a value for the parameter";
+    ToggleBypass => "toggle_bypass", Effects, "Switch the effect on or off";
+    Undo => "undo", Effects, "Undo the last change";
+    Redo => "redo", Effects, "Redo the last undone change";
+    SwitchSnapshot => "switch_snapshot", Effects, "Flip to the other snapshot";
+    CopySnapshot => "copy_snapshot", Effects, "Copy the settings to the other snapshot";
+    NextField => "next_field", Settings, "Select the next setting";
+    PreviousField => "previous_field", Settings, "Select the previous setting";
+    NextValue => "next_value", Settings, "Pick the next value";
+    PreviousValue => "previous_value", Settings, "Pick the previous value";
+    ApplySettings => "apply_settings", Settings, "Restart the streams with these settings";
+    ReloadSettings => "reload_settings", Settings, "Go back to the settings in use";
+    ScrollUp => "scroll_up", Log, "Scroll to older lines";
+    ScrollDown => "scroll_down", Log, "Scroll to newer lines";
+    PageUp => "page_up", Log, "Scroll a page back";
+    PageDown => "page_down", Log, "Scroll a page forward";
+    Oldest => "oldest", Log, "Jump to the oldest line";
+    Newest => "newest", Log, "Jump to the newest line";
+}
+
+const DEFAULT_BINDINGS: &[(Action, &[&str])] = &[
+    (Action::Quit, &["q"]),
+    (Action::NextTab, &["Tab"]),
+    (Action::PreviousTab, &["shift+Tab"]),
+    (Action::Help, &["?"]),
+    (Action::Close, &["Esc"]),
+    (Action::CommandPalette, &[":"]),
+    (Action::NextParam, &["Right"]),
+    (Action::PreviousParam, &["Left"]),
+    (Action::Increase, &["Up"]),
+    (Action::Decrease, &["Down"]),
+    (Action::IncreaseCoarse, &["ctrl+Up"]),
+    (Action::DecreaseCoarse, &["ctrl+Down"]),
+    (Action::IncreaseFine, &["shift+Up"]),
+    (Action::DecreaseFine, &["shift+Down"]),
+    (Action::EnterValue, &["Enter", "="]),
+    (Action::ToggleBypass, &["b"]),
+    (Action::Undo, &["u"]),
+    (Action::Redo, &["ctrl+r"]),
+    (Action::SwitchSnapshot, &["a"]),
+    (Action::CopySnapshot, &["c"]),
+    (Action::NextField, &["Down"]),
+    (Action::PreviousField, &["Up"]),
+    (Action::NextValue, &["Right"]),
+    (Action::PreviousValue, &["Left"]),
+    (Action::ApplySettings, &["Enter"]),
+    (Action::ReloadSettings, &["r"]),
+    (Action::ScrollUp, &["Up"]),
+    (Action::ScrollDown, &["Down"]),
+    (Action::PageUp, &["PageUp"]),
+    (Action::PageDown, &["PageDown"]),
+    (Action::Oldest, &["Home"]),
+    (Action::Newest, &["End"]),
+];
+
+/// A key together with the modifiers held down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    /// Normalises what the terminal reports, so `shift+Tab` and `A` match however they arrive.
+    pub fn from_event(event: KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers =
+            modifiers & (KeyModifiers::SHIFT | KeyModifiers::CONTROL | KeyModifiers::ALT);
+        let code = match code {
+            // The character already says whether shift was held, so `shift+a` is the key that
+            // types `A`.
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            KeyCode::BackTab => {
+                modifiers.insert(KeyModifiers::SHIFT);
+                KeyCode::Tab
+            }
+            _ => code,
+        };
+        Self { code, modifiers }
+    }
+
+    /// Parses keys like `q`, `ctrl+r`, `shift+Up` or `F5`.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text;
+        while let Some((modifier, key)) = rest.split_once('+').filter(|(_, key)| !key.is_empty()) {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "shift" => KeyModifiers::SHIFT,
+                "alt" => KeyModifiers::ALT,
+                _ => anyhow::bail!("unknown modifier {modifier:?} in key {text:?}"),
+            };
+            rest = key;
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_ascii_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "enter" => KeyCode::Enter,
+                "esc" => KeyCode::Esc,
+                "backspace" => KeyCode::Backspace,
+                "delete" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                name => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n) if (1..=24).contains(&n) => KeyCode::F(n),
+                    _ => anyhow::bail!("unknown key {text:?}"),
+                },
+            },
+        };
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "ctrl+"),
+            (KeyModifiers::ALT, "alt+"),
+            (KeyModifiers::SHIFT, "shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::PageUp => f.write_str("PageUp"),
+            KeyCode::PageDown => f.write_str("PageDown"),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            code => write!(f, "{code:?}"),
+        }
+    }
+}
+
+pub struct Keymap {
+    bindings: HashMap<(Context, Key), Action>,
+}
+
+impl Keymap {
+    /// The default bindings, with the actions named in `overrides` bound to the keys given there
+    /// instead.
+    pub fn new(overrides: &HashMap<String, Vec<String>>) -> anyhow::Result<Self> {
+        let mut keys: Vec<(Action, Vec<Key>)> = Vec::new();
+        for &(action, defaults) in DEFAULT_BINDINGS {
+            let keys_for = match overrides.get(action.name()) {
+                Some(texts) => texts
+                    .iter()
+                    .map(|text| Key::parse(text))
+                    .collect::<anyhow::Result<_>>()?,
+                None => defaults
+                    .iter()
+                    .map(|text| Key::parse(text))
+                    .collect::<anyhow::Result<_>>()?,
+            };
+            keys.push((action, keys_for));
+        }
+        if let Some(name) = overrides.keys().find(|name| {
+            Action::ALL
+                .iter()
+                .all(|action| action.name() != name.as_str())
+        }) {
+            anyhow::bail!("unknown action {name:?} in [keys]");
+        }
+
+        let mut bindings = HashMap::new();
+        for (action, keys_for) in &keys {
+            for &key in keys_for {
+                if let Some(other) = bindings.insert((action.context(), key), *action)
+                    && other != *action
+                {
+                    anyhow::bail!(
+                        "{key} is bound to both {} and {}",
+                        other.name(),
+                        action.name()
+                    );
+                }
+            }
+        }
+        // A global key would be shadowed on a screen that binds it too.
+        for (&(context, key), &action) in &bindings {
+            if context == Context::Global {
+                continue;
+            }
+            if let Some(global) = bindings.get(&(Context::Global, key)) {
+                anyhow::bail!(
+                    "{key} is bound to both {} and {}",
+                    global.name(),
+                    action.name()
+                );
+            }
+        }
+        Ok(Self { bindings })
+    }
+
+    /// What `event` does on a screen with `context`.
+    pub fn action(&self, context: Context, event: KeyEvent) -> Option<Action> {
+        let key = Key::from_event(event);
+        self.bindings
+            .get(&(context, key))
+            .or_else(|| self.bindings.get(&(Context::Global, key)))
+            .copied()
+    }
+
+    /// The keys bound to `action`, for showing in help.
+    pub fn keys(&self, action: Action) -> Vec<Key> {
+        let mut keys: Vec<Key> = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|((_, key), _)| *key)
+            .collect();
+        keys.sort_by_key(|key| key.to_string());
+        keys
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new(&HashMap::new()).expect("the default key bindings are valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrides(entries: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+        entries
+            .iter()
+            .map(|(action, keys)| {
+                let keys = keys.iter().map(|key| key.to_string()).collect();
+                (action.to_string(), keys)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_keys_with_modifiers() {
+        let key = Key::parse("ctrl+alt+x").unwrap();
+        assert_eq!(key.code, KeyCode::Char('x'));
+        assert_eq!(key.modifiers, KeyModifiers::CONTROL | KeyModifiers::ALT);
+        assert_eq!(
+            Key::parse("Shift+Up").unwrap().modifiers,
+            KeyModifiers::SHIFT
+        );
+        // Shift is part of a character, and a plus on its own is a key.
+        assert_eq!(Key::parse("shift+a").unwrap(), Key::parse("A").unwrap());
+        assert_ne!(Key::parse("shift+a").unwrap(), Key::parse("a").unwrap());
+        assert_eq!(
+            Key::from_event(KeyEvent::new(KeyCode::Char('A'), KeyModifiers::SHIFT)),
+            Key::parse("shift+a").unwrap()
+        );
+        assert_eq!(Key::parse("ctrl++").unwrap().code, KeyCode::Char('+'));
+        assert_eq!(
+            Key::parse("BackTab").unwrap(),
+            Key::parse("shift+Tab").unwrap()
+        );
+    }
+
+    #[test]
+    fn closing_the_help_can_be_rebound() {
+        let keymap = Keymap::new(&overrides(&[("close", &["x"])])).unwrap();
+        let press = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        assert_eq!(
+            keymap.action(Context::Log, press(KeyCode::Char('x'))),
+            Some(Action::Close)
+        );
+        assert_eq!(keymap.action(Context::Log, press(KeyCode::Esc)), None);
+    }
+
+    #[test]
+    fn parses_named_keys() {
+        assert_eq!(Key::parse("Space").unwrap().code, KeyCode::Char(' '));
+        assert_eq!(Key::parse("pagedown").unwrap().code, KeyCode::PageDown);
+        assert_eq!(Key::parse("F12").unwrap().code, KeyCode::F(12));
+        assert_eq!(Key::parse("ctrl+r").unwrap().to_string(), "ctrl+r");
+    }
+
+    #[test]
+    fn rejects_bad_keys() {
+        for text in ["", "F0", "F25", "Upp", "super+a", "ctrl+"] {
+            assert!(Key::parse(text).is_err(), "{text:?} parsed");
+        }
+    }
+
+    #[test]
+    fn rejects_a_key_bound_twice_on_one_tab() {
+        let err = Keymap::new(&overrides(&[("undo", &["b"])])).err().unwrap();
+        assert_eq!(err.to_string(), "b is bound to both toggle_bypass and undo");
+        let err = Keymap::new(&overrides(&[("oldest", &["q"])]))
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "q is bound to both quit and oldest");
+        // Different tabs can use the same key.
+        assert!(Keymap::new(&overrides(&[("reload_settings", &["u"])])).is_ok());
+    }
+}
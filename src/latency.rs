@@ -0,0 +1,101 @@
+// Automatic sizing of the safety margin between the input and output streams.
+//
+// The margin starts at `--latency`. Underruns grow it straight away; a long stretch where the
+// buffer never came close to running dry shrinks it again, a little at a time.
+
+use crate::stats::Window;
+
+/// How much the margin grows after a window with underruns, as a fraction of itself.
+const GROW_FACTOR: f64 = 0.5;
+/// How much the margin shrinks after enough clean windows, as a fraction of itself.
+const SHRINK_FACTOR: f64 = 0.125;
+/// Clean windows in a row needed before shrinking.
+const SHRINK_AFTER: u32 = 10;
+/// A window is clean if the fill level stayed above this fraction of the margin.
+const HEADROOM: f64 = 0.5;
+/// The margin never goes below this many output callbacks.
+const MIN_PERIODS: usize = 2;
+
+pub struct LatencyController {
+    last_underruns: u64,
+    clean_windows: u32,
+    max_target: usize,
+}
+
+impl LatencyController {
+    /// `max_target` is the largest fill level, in samples, the ring buffer has room for.
+    pub fn new(max_target: usize) -> Self {
+        Self {
+            last_underruns: 0,
+            clean_windows: 0,
+            max_target,
+        }
+    }
+
+    /// Takes the window that just ended and returns the target fill level to use from now on.
+    pub fn update(&mut self, window: Window, target: usize) -> usize {
+        let underran = window.underruns > self.last_underruns;
+        self.last_underruns = window.underruns;
+        let min_target = (window.period_samples * MIN_PERIODS).min(self.max_target);
+
+        if underran {
+            self.clean_windows = 0;
+            let grown = target + (target as f64 * GROW_FACTOR) as usize;
+            return grown.max(min_target).min(self.max_target);
+        }
+
+        match window.fill_min {
+            Some(fill) if fill as f64 > target as f64 * HEADROOM => self.clean_windows += 1,
+            _ => self.clean_windows = 0,
+        }
+        if self.clean_windows < SHRINK_AFTER {
+            return target.max(min_target);
+        }
+        self.clean_windows = 0;
+        let shrunk = target - (target as f64 * SHRINK_FACTOR) as usize;
+        shrunk.max(min_target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(underruns: u64, fill_min: usize) -> Window {
+        Window {
+            underruns,
+            fill_min: Some(fill_min),
+            period_samples: 256,
+        }
+    }
+
+    #[test]
+    fn grows_after_underruns() {
+        let mut controller = LatencyController::new(10_000);
+        assert_eq!(controller.update(window(1, 0), 1_000), 1_500);
+        // Only new underruns count.
+        assert_eq!(controller.update(window(1, 900), 1_500), 1_500);
+        assert_eq!(controller.update(window(3, 0), 8_000), 10_000);
+    }
+
+    #[test]
+    fn shrinks_after_a_stable_stretch() {
+        let mut controller = LatencyController::new(10_000);
+        let mut target = 2_000;
+        for _ in 1..SHRINK_AFTER {
+            target = controller.update(window(0, 1_500), target);
+            assert_eq!(target, 2_000);
+        }
+        assert_eq!(controller.update(window(0, 1_500), target), 1_750);
+
+        // A window that came close to running dry starts the count again.
+        assert_eq!(controller.update(window(0, 100), 1_750), 1_750);
+
+        // Never below two periods.
+        let mut target = 600;
+        for _ in 0..10 * SHRINK_AFTER {
+            target = controller.update(window(0, 600), target);
+        }
+        assert_eq!(target, 512);
+    }
+}
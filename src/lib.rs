@@ -0,0 +1,38 @@
+//! The audio engine, effects and TUI behind the `audio_oxidiser` binary.
+
+// Debug builds abort on allocations in the stream callbacks, see `sample_format`.
+#[cfg(debug_assertions)]
+#[global_allocator]
+static ALLOCATOR: assert_no_alloc::AllocDisabler = assert_no_alloc::AllocDisabler;
+
+pub mod adjust;
+pub mod app;
+pub mod backend;
+pub mod cli;
+pub mod config;
+pub mod devices;
+pub mod drift;
+pub mod effect_params;
+pub mod effect_ui;
+pub mod effects;
+pub mod engine_state;
+pub mod history;
+pub mod keymap;
+pub mod latency;
+pub mod list;
+pub mod log_ui;
+pub mod logging;
+pub mod measure;
+pub mod mouse;
+pub mod overlay_ui;
+pub mod palette;
+pub mod pipeline;
+pub mod resampler;
+pub mod sample_format;
+pub mod settings;
+pub mod settings_ui;
+pub mod snapshots;
+pub mod stats;
+pub mod theme;
+pub mod ui;
+pub mod units;
@@ -0,0 +1,145 @@
+// The `list-hosts`, `list-devices` and `device-info` subcommands, for finding the ids that
+// `--input-device` and `--output-device` take.
+
+use crate::cli::Command;
+use crate::devices::{self, DeviceInfo};
+use cpal::traits::HostTrait;
+use serde_json::{Value, json};
+
+pub fn run(command: &Command) -> anyhow::Result<()> {
+    match command {
+        Command::ListHosts { json } => list_hosts(*json),
+        Command::ListDevices { json } => list_devices(*json),
+        Command::DeviceInfo { id, json } => device_info(id, *json),
+        Command::MeasureLatency { .. } => anyhow::bail!("measure-latency is not a listing command"),
+    }
+}
+
+fn list_hosts(json: bool) -> anyhow::Result<()> {
+    let default = cpal::default_host().id();
+    let hosts = cpal::available_hosts();
+    if json {
+        let hosts: Vec<Value> = hosts
+            .iter()
+            .map(|&id| {
+                json!({
+                    "id": id.to_string(),
+                    "name": id.name(),
+                    "default": id == default,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&hosts)?);
+    } else {
+        for id in hosts {
+            let marker = if id == default { " (default)" } else { "" };
+            println!("{id}\t{}{marker}", id.name());
+        }
+    }
+    Ok(())
+}
+
+fn list_devices(json: bool) -> anyhow::Result<()> {
+    let mut hosts = Vec::new();
+    for id in cpal::available_hosts() {
+        let host = cpal::host_from_id(id)?;
+        hosts.push((id, devices::list_devices(&host)?));
+    }
+
+    if json {
+        let hosts: Vec<Value> = hosts
+            .iter()
+            .map(|(id, devices)| {
+                json!({
+                    "host": id.to_string(),
+                    "devices": devices.iter().map(device_json).collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&hosts)?);
+        return Ok(());
+    }
+
+    for (id, devices) in hosts {
+        println!("{}:", id.name());
+        for device in devices {
+            println!("  {}\t{}", device.id, device.name);
+            if let Some(config) = &device.default_input {
+                println!("      input:  {}", devices::describe_config(config));
+            }
+            if let Some(config) = &device.default_output {
+                println!("      output: {}", devices::describe_config(config));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn device_info(id: &str, json: bool) -> anyhow::Result<()> {
+    let id: cpal::DeviceId = id
+        .parse()
+        .map_err(|err| anyhow::anyhow!("failed to parse device id: {err}"))?;
+    let host = cpal::host_from_id(id.0)?;
+    let device = host
+        .device_by_id(&id)
+        .and_then(|device| DeviceInfo::from_device(&device))
+        .ok_or_else(|| anyhow::anyhow!("no device with id {id}"))?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&device_json(&device))?);
+        return Ok(());
+    }
+
+    println!("id:     {}", device.id);
+    println!("name:   {}", device.name);
+    println!("host:   {}", device.id.0.name());
+    for (direction, default, ranges) in [
+        ("input", &device.default_input, &device.input_configs),
+        ("output", &device.default_output, &device.output_configs),
+    ] {
+        if ranges.is_empty() {
+            continue;
+        }
+        println!("{direction}:");
+        if let Some(config) = default {
+            println!("  default: {}", devices::describe_config(config));
+        }
+        for range in ranges {
+            println!("  {}", devices::describe_range(range));
+        }
+    }
+    Ok(())
+}
+
+fn device_json(device: &DeviceInfo) -> Value {
+    json!({
+        "id": device.id.to_string(),
+        "name": device.name,
+        "default_input": device.default_input.as_ref().map(config_json),
+        "default_output": device.default_output.as_ref().map(config_json),
+        "input_configs": device.input_configs.iter().map(range_json).collect::<Vec<_>>(),
+        "output_configs": device.output_configs.iter().map(range_json).collect::<Vec<_>>(),
+    })
+}
+
+fn config_json(config: &cpal::SupportedStreamConfig) -> Value {
+    json!({
+        "channels": config.channels(),
+        "sample_rate": config.sample_rate(),
+        "sample_format": config.sample_format().to_string(),
+    })
+}
+
+fn range_json(range: &cpal::SupportedStreamConfigRange) -> Value {
+    let buffer_size = match *range.buffer_size() {
+        cpal::SupportedBufferSize::Range { min, max } => json!({ "min": min, "max": max }),
+        cpal::SupportedBufferSize::Unknown => Value::Null,
+    };
+    json!({
+        "channels": range.channels(),
+        "min_sample_rate": range.min_sample_rate(),
+        "max_sample_rate": range.max_sample_rate(),
+        "sample_format": range.sample_format().to_string(),
+        "buffer_size": buffer_size,
+    })
+}
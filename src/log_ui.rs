@@ -0,0 +1,46 @@
+use crate::app::App;
+use crate::logging;
+use log::Level;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::Style,
+    text::{Line, Span},
+    widgets::{Block, Paragraph},
+};
+
+pub fn draw_log(frame: &mut Frame, app: &mut App, area: Rect) {
+    let height = area.height.saturating_sub(2) as usize;
+    let theme = app.theme.clone();
+    let (lines, total) = logging::with_lines(|lines| {
+        // Scrolling counts back from the newest line, so new messages don't move the view
+        // while it is scrolled.
+        let scroll = app.log_scroll.min(lines.len().saturating_sub(height));
+        app.log_scroll = scroll;
+        let end = lines.len() - scroll;
+        let start = end.saturating_sub(height);
+        let visible: Vec<Line> = lines
+            .range(start..end)
+            .map(|line| {
+                let style = match line.level {
+                    Level::Error => theme.error,
+                    Level::Warn => theme.warning,
+                    Level::Info => Style::default(),
+                    Level::Debug | Level::Trace => theme.muted,
+                };
+                Line::styled(line.text.clone(), style)
+            })
+            .collect();
+        (visible, lines.len())
+    });
+
+    let position = if app.log_scroll == 0 {
+        " newest ".to_string()
+    } else {
+        format!(" {} of {total} ", total - app.log_scroll)
+    };
+    let block = Block::bordered()
+        .title(Span::styled("Log", theme.title))
+        .title_bottom(Line::from(position).right_aligned());
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
@@ -0,0 +1,324 @@
+// Diagnostics, routed through the `log` facade to a rotating log file and to the log tab.
+//
+// Nothing may print to the terminal while the TUI owns it. The stream callbacks don't log at all:
+// they push plain `AudioEvent`s into a lock-free queue, and the pipeline thread turns those into
+// log messages.
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use ringbuf::{
+    HeapCons, HeapProd, HeapRb,
+    traits::{Consumer, Producer, Split},
+};
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The log file is rotated once it grows past this many bytes.
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+/// How many rotated files are kept next to the current one.
+const KEEP_FILES: usize = 3;
+/// How many lines the log tab can scroll back through.
+const MAX_LINES: usize = 1000;
+/// How many events a stream callback can queue before the pipeline thread picks them up.
+const EVENT_CAPACITY: usize = 256;
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// A logged message, as kept for the log tab.
+#[derive(Clone, Debug)]
+pub struct LogLine {
+    pub level: Level,
+    pub text: String,
+}
+
+struct Logger {
+    level: LevelFilter,
+    file: Mutex<Option<LogFile>>,
+    lines: Mutex<VecDeque<LogLine>>,
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+/// Installs the logger. Without a file the messages only go to the log tab.
+pub fn init(path: Option<&Path>, level: LevelFilter) -> anyhow::Result<()> {
+    let file = path.map(LogFile::open).transpose()?;
+    let logger = LOGGER.get_or_init(|| Logger {
+        level,
+        file: Mutex::new(file),
+        lines: Mutex::new(VecDeque::new()),
+    });
+    log::set_logger(logger).map_err(|err| anyhow::anyhow!("failed to set up logging: {err}"))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// `audio_oxidiser/audio_oxidiser.log` in `$XDG_STATE_HOME`, or in `~/.local/state` if that
+/// isn't set. The shared temporary directory is only used without a home directory.
+pub fn default_path() -> PathBuf {
+    state_dir(std::env::var_os("XDG_STATE_HOME"), std::env::var_os("HOME"))
+        .join("audio_oxidiser")
+        .join("audio_oxidiser.log")
+}
+
+/// Where state goes, given the values of `$XDG_STATE_HOME` and `$HOME`.
+fn state_dir(xdg_state_home: Option<OsString>, home: Option<OsString>) -> PathBuf {
+    xdg_state_home
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home.map(|home| PathBuf::from(home).join(".local").join("state")))
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// Calls `f` with the messages kept for the log tab, oldest first.
+pub fn with_lines<R>(f: impl FnOnce(&VecDeque<LogLine>) -> R) -> R {
+    match LOGGER.get() {
+        Some(logger) => f(&logger.lines.lock().unwrap()),
+        None => f(&VecDeque::new()),
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let text = format!("{} {:<5} {}", timestamp(), record.level(), record.args());
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            // There is nowhere left to report a failing log file to.
+            let _ = file.write_line(&text);
+        }
+
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == MAX_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(LogLine {
+            level: record.level(),
+            text,
+        });
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+impl LogFile {
+    fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|err| {
+                anyhow::anyhow!("failed to create log directory {}: {err}", dir.display())
+            })?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| anyhow::anyhow!("failed to open log file {}: {err}", path.display()))?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.size > MAX_FILE_SIZE {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{line}")?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    /// Shifts `name.1` to `name.2` and so on, dropping the oldest, and starts a fresh file.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let rotated = |n: usize| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{n}"));
+            PathBuf::from(name)
+        };
+        for n in (1..KEEP_FILES).rev() {
+            let _ = fs::rename(rotated(n), rotated(n + 1));
+        }
+        fs::rename(&self.path, rotated(1))?;
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Time of day in UTC, e.g. `14:03:59.120`.
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = now.as_secs() % 86_400;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        seconds / 3_600,
+        seconds / 60 % 60,
+        seconds % 60,
+        now.subsec_millis()
+    )
+}
+
+/// Something a stream callback wants logged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioEvent {
+    /// The output had nothing to play.
+    Underrun,
+    /// The input had nowhere to put its samples.
+    Overrun,
+}
+
+/// The callback's end of an event queue. Sending never blocks or allocates; events that don't
+/// fit are dropped.
+pub struct EventSender(HeapProd<AudioEvent>);
+
+/// The pipeline thread's end of an event queue.
+pub struct EventReceiver(HeapCons<AudioEvent>);
+
+pub fn event_queue() -> (EventSender, EventReceiver) {
+    let (producer, consumer) = HeapRb::new(EVENT_CAPACITY).split();
+    (EventSender(producer), EventReceiver(consumer))
+}
+
+impl EventSender {
+    pub fn send(&mut self, event: AudioEvent) {
+        let _ = self.0.try_push(event);
+    }
+}
+
+impl EventReceiver {
+    /// Logs everything queued since the last call, with repeats folded into a count.
+    pub fn log_pending(&mut self) {
+        for message in self.pending() {
+            log::warn!("{message}");
+        }
+    }
+
+    /// Takes everything queued since the last call, as one message per kind of event.
+    fn pending(&mut self) -> Vec<String> {
+        let (mut underruns, mut overruns) = (0, 0);
+        for event in self.0.pop_iter() {
+            match event {
+                AudioEvent::Underrun => underruns += 1,
+                AudioEvent::Overrun => overruns += 1,
+            }
+        }
+        let mut messages = Vec::new();
+        if underruns > 0 {
+            messages.push(format!("input stream fell behind: {}", count(underruns)));
+        }
+        if overruns > 0 {
+            messages.push(format!("output stream fell behind: {}", count(overruns)));
+        }
+        messages
+    }
+}
+
+/// How many times an event happened. A full queue may have dropped some.
+fn count(n: usize) -> String {
+    let more = if n >= EVENT_CAPACITY { "+" } else { "" };
+    format!("{n}{more} time(s)")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for one test to write its files in.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("audio_oxidiser-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn rotates_past_the_size_limit_keeping_three_files() {
+        let dir = temp_dir("rotate");
+        let path = dir.join("test.log");
+        let mut file = LogFile::open(&path).unwrap();
+        file.write_line("first").unwrap();
+        assert!(!dir.join("test.log.1").exists());
+
+        for n in 0..5 {
+            file.size = MAX_FILE_SIZE + 1;
+            file.write_line(&format!("line {n}")).unwrap();
+        }
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("test.log"), "line 4\n");
+        assert_eq!(read("test.log.1"), "line 3\n");
+        assert_eq!(read("test.log.2"), "line 2\n");
+        assert_eq!(read("test.log.3"), "line 1\n");
+        assert!(!dir.join("test.log.4").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn state_goes_in_xdg_state_home_then_home() {
+        let home = || Some(OsString::from("/home/me"));
+        assert_eq!(
+            state_dir(Some("/state".into()), home()),
+            PathBuf::from("/state")
+        );
+        assert_eq!(
+            state_dir(None, home()),
+            PathBuf::from("/home/me/.local/state")
+        );
+        assert_eq!(
+            state_dir(Some("".into()), home()),
+            PathBuf::from("/home/me/.local/state")
+        );
+        assert_eq!(state_dir(None, None), std::env::temp_dir());
+    }
+
+    #[test]
+    fn folds_repeated_events_into_a_count() {
+        let (mut sender, mut receiver) = event_queue();
+        for _ in 0..3 {
+            sender.send(AudioEvent::Underrun);
+        }
+        sender.send(AudioEvent::Overrun);
+        assert_eq!(
+            receiver.pending(),
+            [
+                "input stream fell behind: 3 time(s)",
+                "output stream fell behind: 1 time(s)"
+            ]
+        );
+        assert!(receiver.pending().is_empty());
+    }
+
+    #[test]
+    fn a_full_queue_counts_as_at_least_that_many() {
+        let (mut sender, mut receiver) = event_queue();
+        for _ in 0..EVENT_CAPACITY + 10 {
+            sender.send(AudioEvent::Overrun);
+        }
+        assert_eq!(
+            receiver.pending(),
+            [format!(
+                "output stream fell behind: {EVENT_CAPACITY}+ time(s)"
+            )]
+        );
+    }
+}
@@ -1,22 +1,36 @@
 //! Feeds back the input stream directly into the output stream.
 //!
-//! Assumes that the input and output devices can use the same stream configuration and that they
-//! support the f32 sample format.
+//! The input and output devices each get their own stream configuration; audio is channel mapped
+//! and resampled between them when they differ. Samples are converted to and from f32 at the
+//! stream boundary, so devices that only expose integer formats work too.
 //!
 //! Uses a delay of `LATENCY_MS` milliseconds in case the default input and output streams are not
 //! precisely synchronised.
-use effect_params::EffectParams;
-use std::sync::{Arc, atomic::AtomicBool};
+use audio_oxidiser::config::Config;
+use audio_oxidiser::effect_params::EffectParams;
+use audio_oxidiser::engine_state::EngineState;
+use audio_oxidiser::{app, backend, cli, list, logging, measure, pipeline};
+use clap::Parser;
+use std::sync::{Arc, atomic::AtomicBool, mpsc};
 use std::thread;
 
-mod app;
-mod effect_params;
-mod effect_ui;
-mod effects;
-mod pipeline;
-mod ui;
-
 fn main() -> anyhow::Result<()> {
+    let opt = cli::Opt::parse();
+    match &opt.command {
+        Some(cli::Command::MeasureLatency { signal, json }) => {
+            return measure::run(&opt, *signal, *json);
+        }
+        Some(command) => return list::run(command),
+        None => {}
+    }
+
+    let log_file = opt.log_file.clone().unwrap_or_else(logging::default_path);
+    logging::init(Some(&log_file), opt.log_level)?;
+
+    let config = Config::load(opt.config.as_deref())?;
+    // Open the backend up front so a bad file or missing JACK is reported before the UI starts.
+    let backend = backend::from_opt(&opt)?;
+
     let running = Arc::new(AtomicBool::new(true));
     let pipeline_running = Arc::clone(&running);
     let ui_running = Arc::clone(&running);
@@ -25,13 +39,29 @@ fn main() -> anyhow::Result<()> {
     let ui_params = Arc::clone(&params);
     let pipeline_params = Arc::clone(&params);
 
+    let engine = Arc::new(EngineState::new());
+    let ui_engine = Arc::clone(&engine);
+    let pipeline_engine = Arc::clone(&engine);
+
+    let (commands, pipeline_commands) = mpsc::channel();
+
     let pipeline_handle = thread::Builder::new()
         .name("pipeline".to_string())
-        .spawn(move || pipeline::init_pipeline(pipeline_running, pipeline_params).unwrap())
+        .spawn(move || {
+            pipeline::init_pipeline(
+                pipeline_running,
+                pipeline_params,
+                pipeline_engine,
+                pipeline_commands,
+                opt,
+                backend,
+            )
+            .unwrap()
+        })
         .unwrap();
     let ui_handle = thread::Builder::new()
         .name("ui".to_string())
-        .spawn(move || app::init_ui(ui_running, ui_params).unwrap())
+        .spawn(move || app::init_ui(ui_running, ui_params, ui_engine, commands, config).unwrap())
         .unwrap();
 
     pipeline_handle.join().unwrap();
@@ -0,0 +1,309 @@
+// The `measure-latency` subcommand: plays a test signal through the output, records it back
+// through a physical or software loopback and finds it again by cross-correlation.
+//
+// The round trip is measured from the moment the output callback writes the signal to the moment
+// the input callback could have read it, so it covers the devices and drivers but none of the
+// app's own buffering. That share is worked out from the options the app would run with.
+
+use crate::backend::cpal_backend;
+use crate::cli::{BackendKind, Opt, Signal};
+use crate::pipeline;
+use crate::resampler::Resampler;
+use crate::sample_format;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use realfft::RealFftPlanner;
+use ringbuf::{
+    HeapRb,
+    traits::{Consumer, Producer, Split},
+};
+use serde_json::json;
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Silence played before the signal so both streams have settled.
+const LEAD_IN: Duration = Duration::from_millis(500);
+/// Longest round trip that can be detected.
+const MAX_ROUND_TRIP: Duration = Duration::from_secs(1);
+/// Order of the maximum length sequence, giving 2^12 - 1 samples.
+const MLS_ORDER: u32 = 12;
+const AMPLITUDE: f32 = 0.5;
+/// The correlation peak must stand this far above the average to count as found.
+const MIN_PEAK_RATIO: f32 = 8.0;
+
+pub fn run(opt: &Opt, signal: Signal, json: bool) -> anyhow::Result<()> {
+    if matches!(opt.backend, BackendKind::Null | BackendKind::File) {
+        anyhow::bail!("measuring latency needs real devices, use the cpal or jack backend");
+    }
+    let host = cpal_backend::select_host(opt)?;
+    let settings = pipeline::initial_settings(host.id(), opt)?;
+    let input_device = find_device(&host, settings.input_device.as_ref(), true)?;
+    let output_device = find_device(&host, settings.output_device.as_ref(), false)?;
+
+    let input_config = cpal_backend::negotiate_input_config(&input_device, &settings)?;
+    let output_config =
+        cpal_backend::negotiate_output_config(&output_device, input_config.sample_rate, &settings)?;
+    if output_config.sample_rate != input_config.sample_rate {
+        anyhow::bail!(
+            "the output doesn't run at {} Hz like the input, pick a rate both support with --sample-rate",
+            input_config.sample_rate
+        );
+    }
+    let sample_rate = input_config.sample_rate;
+    let rate = sample_rate as f64;
+    let input_channels = input_config.channels as usize;
+    let output_channels = output_config.channels as usize;
+
+    let reference = match signal {
+        Signal::Impulse => vec![AMPLITUDE],
+        Signal::Mls => mls(),
+    };
+    let lead = (LEAD_IN.as_secs_f64() * rate) as usize;
+    let capture_frames = lead + reference.len() + (MAX_ROUND_TRIP.as_secs_f64() * rate) as usize;
+
+    // Times are nanoseconds since `base`, so the callbacks can share them through atomics.
+    let base = Instant::now();
+    let nanos = move || base.elapsed().as_nanos() as u64;
+    let frames_to_nanos = move |frames: usize| (frames as f64 * 1e9 / rate) as u64;
+
+    let written_at = Arc::new(AtomicU64::new(u64::MAX));
+    let period = Arc::new(AtomicUsize::new(0));
+    let output_written_at = Arc::clone(&written_at);
+    let output_period = Arc::clone(&period);
+    let output_reference = reference.clone();
+    let mut position = 0;
+    let output_data_fn = move |data: &mut [f32]| {
+        let now = nanos();
+        let frames = data.len() / output_channels;
+        output_period.store(frames, Ordering::Relaxed);
+        if (position..position + frames).contains(&lead) {
+            output_written_at.store(now + frames_to_nanos(lead - position), Ordering::Relaxed);
+        }
+        for (i, frame) in data.chunks_exact_mut(output_channels).enumerate() {
+            let sample = (position + i)
+                .checked_sub(lead)
+                .and_then(|j| output_reference.get(j))
+                .copied()
+                .unwrap_or(0.0);
+            frame.fill(sample);
+        }
+        position += frames;
+    };
+
+    // The input is mixed down to mono and handed over along with when each callback ran.
+    let (mut samples, mut recorded) = HeapRb::<f32>::new(capture_frames).split();
+    let (mut times, mut callbacks) = HeapRb::<(usize, u64)>::new(capture_frames).split();
+    let mut captured = 0;
+    let input_data_fn = move |data: &[f32]| {
+        let _ = times.try_push((captured, nanos()));
+        for frame in data.chunks_exact(input_channels) {
+            let _ = samples.try_push(frame.iter().sum::<f32>());
+        }
+        captured += data.len() / input_channels;
+    };
+
+    let err_fn = |err: cpal::StreamError| eprintln!("stream error: {err}");
+    let input_format = sample_format::input_format(&input_device, &input_config)?;
+    let output_format = sample_format::output_format(&output_device, &output_config)?;
+    let input_stream = sample_format::build_input_stream(
+        &input_device,
+        &input_config,
+        input_format,
+        input_data_fn,
+        err_fn,
+    )?;
+    let output_stream = sample_format::build_output_stream(
+        &output_device,
+        &output_config,
+        output_format,
+        output_data_fn,
+        err_fn,
+    )?;
+    input_stream.play()?;
+    output_stream.play()?;
+    thread::sleep(Duration::from_secs_f64(capture_frames as f64 / rate) + LEAD_IN);
+    drop(input_stream);
+    drop(output_stream);
+
+    let written_at = written_at.load(Ordering::Relaxed);
+    if written_at == u64::MAX {
+        anyhow::bail!("the output stream never got to play the signal");
+    }
+    let recording: Vec<f32> = recorded.pop_iter().collect();
+    let callbacks: Vec<(usize, u64)> = callbacks.pop_iter().collect();
+    let (found, peak_ratio) = find_signal(&recording, &reference)
+        .ok_or_else(|| anyhow::anyhow!("recorded too little audio to look for the signal in"))?;
+    if peak_ratio < MIN_PEAK_RATIO {
+        anyhow::bail!(
+            "the signal didn't come back, check that the output is looped back to the input"
+        );
+    }
+
+    // The input callback runs once its buffer is full, so a frame was captured as long before
+    // the callback as there are frames after it in the buffer.
+    let callback = callbacks.partition_point(|&(start, _)| start <= found) - 1;
+    let (_, called_at) = callbacks[callback];
+    let buffer_end = callbacks
+        .get(callback + 1)
+        .map_or(recording.len(), |&(start, _)| start);
+    let captured_at = called_at.saturating_sub(frames_to_nanos(buffer_end - found));
+    let round_trip_ms = captured_at.saturating_sub(written_at) as f64 / 1e6;
+
+    let (app_ms, app_source) = if opt.duplex {
+        let frames = period.load(Ordering::Relaxed);
+        (frames as f64 * 1_000.0 / rate, "duplex period")
+    } else {
+        let same_device = input_device.id().ok() == output_device.id().ok();
+        let resampler = Resampler::new(
+            sample_rate,
+            sample_rate,
+            output_channels,
+            !opt.no_drift_compensation && !same_device,
+        )?;
+        let resampler_ms = resampler.latency_frames() as f64 * 1_000.0 / rate;
+        (opt.latency as f64 + resampler_ms, "ring buffer")
+    };
+    let signal_name = match signal {
+        Signal::Impulse => "impulse",
+        Signal::Mls => "mls",
+    };
+
+    if json {
+        let report = json!({
+            "signal": signal_name,
+            "sample_rate": sample_rate,
+            "round_trip_ms": round_trip_ms,
+            "round_trip_frames": (round_trip_ms * rate / 1_000.0).round(),
+            "app_buffering_ms": app_ms,
+            "app_buffering_source": app_source,
+            "total_ms": round_trip_ms + app_ms,
+            "peak_ratio": peak_ratio,
+        });
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!(
+        "signal:        {signal_name}, {} samples at {sample_rate} Hz",
+        reference.len()
+    );
+    println!(
+        "round trip:    {round_trip_ms:.2} ms ({:.0} frames)",
+        round_trip_ms * rate / 1_000.0
+    );
+    println!("app buffering: {app_ms:.2} ms ({app_source})");
+    println!("total:         {:.2} ms", round_trip_ms + app_ms);
+    Ok(())
+}
+
+/// Looks up the requested device, or the default one. Unlike the pipeline this doesn't fall back
+/// to the default when the requested device is missing, since that would measure the wrong one.
+fn find_device(
+    host: &cpal::Host,
+    id: Option<&cpal::DeviceId>,
+    input: bool,
+) -> anyhow::Result<cpal::Device> {
+    let kind = if input { "input" } else { "output" };
+    match id {
+        Some(id) => host
+            .device_by_id(id)
+            .ok_or_else(|| anyhow::anyhow!("no {kind} device with id {id}")),
+        None => if input {
+            host.default_input_device()
+        } else {
+            host.default_output_device()
+        }
+        .ok_or_else(|| anyhow::anyhow!("no default {kind} device")),
+    }
+}
+
+/// A maximum length sequence of `2^MLS_ORDER - 1` samples from a linear feedback shift register
+/// with taps 12, 11, 10 and 4, which only make a maximum length sequence for order 12.
+fn mls() -> Vec<f32> {
+    let mut state: u32 = 1;
+    (0..(1 << MLS_ORDER) - 1)
+        .map(|_| {
+            let bit = state & 1;
+            let feedback = (state ^ (state >> 1) ^ (state >> 2) ^ (state >> 8)) & 1;
+            state = (state >> 1) | (feedback << (MLS_ORDER - 1));
+            if bit == 1 { AMPLITUDE } else { -AMPLITUDE }
+        })
+        .collect()
+}
+
+/// Cross-correlates `recording` with `reference` and returns where the reference starts in the
+/// recording, along with how far the peak stands above the average correlation.
+fn find_signal(recording: &[f32], reference: &[f32]) -> Option<(usize, f32)> {
+    let lags = recording.len().checked_sub(reference.len())? + 1;
+
+    // Multiplying the recording's spectrum by the conjugate of the reference's correlates them
+    // for every lag at once. Padding past both lengths keeps the ends from wrapping around.
+    let len = (recording.len() + reference.len()).next_power_of_two();
+    let mut planner = RealFftPlanner::<f32>::new();
+    let forward = planner.plan_fft_forward(len);
+    let mut input = forward.make_input_vec();
+    input[..recording.len()].copy_from_slice(recording);
+    let mut spectrum = forward.make_output_vec();
+    forward.process(&mut input, &mut spectrum).ok()?;
+    input.fill(0.0);
+    input[..reference.len()].copy_from_slice(reference);
+    let mut reference_spectrum = forward.make_output_vec();
+    forward.process(&mut input, &mut reference_spectrum).ok()?;
+    for (bin, reference_bin) in spectrum.iter_mut().zip(&reference_spectrum) {
+        *bin *= reference_bin.conj();
+    }
+    // Both ends of a real signal's spectrum are real, rounding aside. The inverse comes out
+    // scaled by `len`, which the peak ratio doesn't mind.
+    spectrum[0].im = 0.0;
+    spectrum[len / 2].im = 0.0;
+    let inverse = planner.plan_fft_inverse(len);
+    let mut correlation = inverse.make_output_vec();
+    inverse.process(&mut spectrum, &mut correlation).ok()?;
+    correlation.truncate(lags);
+    for value in &mut correlation {
+        *value = value.abs();
+    }
+
+    let (peak, &peak_value) = correlation
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    let mean = correlation.iter().sum::<f32>() / correlation.len() as f32;
+    Some((peak, peak_value / mean.max(f32::EPSILON)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mls_has_maximum_length() {
+        let sequence = mls();
+        assert_eq!(sequence.len(), 4095);
+        // One more high than low, and flat circular autocorrelation away from zero lag.
+        let sum: f32 = sequence.iter().sum();
+        assert_eq!(sum, AMPLITUDE);
+        for lag in [1, 2, 100, 4094] {
+            let correlation: f32 = (0..sequence.len())
+                .map(|i| sequence[i] * sequence[(i + lag) % sequence.len()])
+                .sum();
+            assert_eq!(correlation, -AMPLITUDE * AMPLITUDE, "lag {lag}");
+        }
+    }
+
+    #[test]
+    fn finds_the_signal_in_a_recording() {
+        let reference = mls();
+        let mut recording: Vec<f32> = (0..20_000).map(|i| (i as f32 * 0.1).sin() * 0.1).collect();
+        for (i, sample) in reference.iter().enumerate() {
+            recording[12_345 + i] += sample * 0.3;
+        }
+        let (found, peak_ratio) = find_signal(&recording, &reference).unwrap();
+        assert_eq!(found, 12_345);
+        assert!(peak_ratio > MIN_PEAK_RATIO, "peak ratio was {peak_ratio}");
+        assert!(find_signal(&recording[..100], &reference).is_none());
+    }
+}
@@ -0,0 +1,137 @@
+// Where the clickable parts of the screen were last drawn, and what a click or drag is doing.
+
+use crate::effect_params::Param;
+use ratatui::layout::{Position, Rect};
+use std::time::{Duration, Instant};
+
+/// Two clicks on the same knob closer together than this reset it.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+/// The share of a parameter's range one row of vertical dragging covers.
+const DRAG_PER_ROW: f32 = 0.05;
+
+/// A knob on screen and the parameter behind it.
+#[derive(Clone, Copy, Debug)]
+pub struct KnobArea {
+    pub area: Rect,
+    /// Position of the knob on its tab, as in `ParamSelection`.
+    pub index: usize,
+    pub param: Param,
+}
+
+/// Filled in while drawing, then used to find what the mouse is over.
+#[derive(Default)]
+pub struct HitAreas {
+    pub tabs: Vec<Rect>,
+    pub knobs: Vec<KnobArea>,
+}
+
+impl HitAreas {
+    pub fn clear(&mut self) {
+        self.tabs.clear();
+        self.knobs.clear();
+    }
+
+    pub fn tab_at(&self, position: Position) -> Option<usize> {
+        self.tabs.iter().position(|area| area.contains(position))
+    }
+
+    pub fn knob_at(&self, position: Position) -> Option<KnobArea> {
+        self.knobs
+            .iter()
+            .find(|knob| knob.area.contains(position))
+            .copied()
+    }
+}
+
+/// A knob being dragged, with where the drag started.
+#[derive(Clone, Copy, Debug)]
+pub struct Drag {
+    pub param: Param,
+    pub row: u16,
+    pub value: f32,
+}
+
+impl Drag {
+    /// The value with the mouse now on `row` of the screen, within `(min, max)`: dragging up
+    /// raises it.
+    pub fn value_at(&self, row: u16, (min, max): (f32, f32)) -> f32 {
+        let rows = self.row as f32 - row as f32;
+        (self.value + rows * DRAG_PER_ROW * (max - min)).clamp(min, max)
+    }
+}
+
+/// The last click on a knob, to spot double clicks.
+#[derive(Clone, Copy, Debug)]
+pub struct Click {
+    pub param: Param,
+    pub at: Instant,
+}
+
+impl Click {
+    /// Whether a click on `param` at `at` follows this one closely enough to be a double click.
+    pub fn is_double(&self, param: Param, at: Instant) -> bool {
+        self.param == param && at.duration_since(self.at) < DOUBLE_CLICK
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn knob(x: u16, index: usize, param: Param) -> KnobArea {
+        KnobArea {
+            area: Rect::new(x, 2, 10, 5),
+            index,
+            param,
+        }
+    }
+
+    #[test]
+    fn finds_what_is_under_the_mouse() {
+        let areas = HitAreas {
+            tabs: vec![Rect::new(0, 0, 8, 1), Rect::new(8, 0, 8, 1)],
+            knobs: vec![
+                knob(0, 0, Param::DistortionLevel),
+                knob(10, 1, Param::DistortionDrive),
+            ],
+        };
+        assert_eq!(areas.tab_at(Position::new(0, 0)), Some(0));
+        assert_eq!(areas.tab_at(Position::new(9, 0)), Some(1));
+        assert_eq!(areas.tab_at(Position::new(16, 0)), None);
+        assert_eq!(areas.tab_at(Position::new(3, 1)), None);
+        let index = |x, y| areas.knob_at(Position::new(x, y)).map(|knob| knob.index);
+        assert_eq!(index(9, 2), Some(0));
+        assert_eq!(index(10, 6), Some(1));
+        assert_eq!(index(12, 7), None);
+        assert_eq!(index(20, 4), None);
+    }
+
+    #[test]
+    fn dragging_up_raises_the_value() {
+        let drag = Drag {
+            param: Param::DelayTime,
+            row: 10,
+            value: 0.5,
+        };
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-6;
+        assert_eq!(drag.value_at(10, (0.0, 1.0)), 0.5);
+        assert!(close(drag.value_at(8, (0.0, 1.0)), 0.6));
+        assert!(close(drag.value_at(12, (0.0, 1.0)), 0.4));
+        // A row covers the same share of any range.
+        assert!(close(drag.value_at(8, (0.0, 2.0)), 0.7));
+        assert_eq!(drag.value_at(0, (0.0, 1.0)), 1.0);
+        assert_eq!(drag.value_at(40, (0.0, 1.0)), 0.0);
+    }
+
+    #[test]
+    fn double_clicks_are_quick_and_on_one_knob() {
+        let at = Instant::now();
+        let click = Click {
+            param: Param::DelayTime,
+            at,
+        };
+        assert!(click.is_double(Param::DelayTime, at + Duration::from_millis(300)));
+        assert!(!click.is_double(Param::DelayTime, at + DOUBLE_CLICK));
+        assert!(!click.is_double(Param::DelayDecay, at + Duration::from_millis(100)));
+    }
+}
@@ -0,0 +1,125 @@
+use crate::app::{App, Overlay};
+use crate::keymap::{Action, Context};
+use crate::palette::Palette;
+use ratatui::{
+    Frame,
+    layout::{
+        Constraint::{Length, Min, Percentage},
+        Flex, Layout, Rect,
+    },
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph},
+};
+
+/// Draws the help or the command palette over the rest of the screen, if one is open.
+pub fn draw_overlay(frame: &mut Frame, app: &App, area: Rect) {
+    match &app.overlay {
+        Overlay::None => {}
+        Overlay::Help { scroll } => draw_help(frame, app, *scroll, centered(area)),
+        Overlay::Palette(palette) => draw_palette(frame, app, palette, centered(area)),
+    }
+}
+
+fn centered(area: Rect) -> Rect {
+    let [area] = Layout::vertical([Percentage(70)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::horizontal([Length(area.width.clamp(40, 80))])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+fn draw_help(frame: &mut Frame, app: &App, scroll: usize, area: Rect) {
+    let close: Vec<String> = app
+        .keymap
+        .keys(Action::Close)
+        .iter()
+        .map(|key| key.to_string())
+        .collect();
+    let title = if close.is_empty() {
+        "Keys".to_string()
+    } else {
+        format!("Keys ({} to close)", close.join("/"))
+    };
+    let block = Block::bordered().title(Span::styled(title, app.theme.title));
+    let mut lines = Vec::new();
+    for (context, heading) in [
+        (app.context(), context_name(app.context())),
+        (Context::Global, "Everywhere"),
+    ] {
+        lines.push(Line::styled(
+            heading,
+            Style::default().add_modifier(Modifier::BOLD),
+        ));
+        for &action in Action::ALL
+            .iter()
+            .filter(|action| action.context() == context)
+        {
+            let keys: Vec<String> = app
+                .keymap
+                .keys(action)
+                .iter()
+                .map(|key| key.to_string())
+                .collect();
+            lines.push(Line::from(vec![
+                Span::styled(format!("  {:<20}", keys.join(", ")), app.theme.accent),
+                Span::raw(action.description()),
+            ]));
+        }
+        lines.push(Line::default());
+    }
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines)
+            .block(block)
+            .scroll((scroll.min(u16::MAX as usize) as u16, 0)),
+        area,
+    );
+}
+
+fn context_name(context: Context) -> &'static str {
+    match context {
+        Context::Global => "Everywhere",
+        Context::Effects => "Effects",
+        Context::Settings => "Settings",
+        Context::Log => "Log",
+    }
+}
+
+fn draw_palette(frame: &mut Frame, app: &App, palette: &Palette, area: Rect) {
+    let block = Block::bordered().title(Span::styled("Commands", app.theme.title));
+    let inner = block.inner(area);
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+    let rows = Layout::vertical([Length(1), Length(1), Min(0)]).split(inner);
+
+    frame.render_widget(Line::from(format!(":{}_", palette.query)), rows[0]);
+    if let Some(error) = &palette.error {
+        frame.render_widget(Line::styled(error.as_str(), app.theme.error), rows[1]);
+    }
+
+    let matches = palette.matches(app.context(), &app.keymap);
+    // Scroll the list just enough to keep the selection in view.
+    let height = rows[2].height as usize;
+    let first = (palette.selected + 1).saturating_sub(height);
+    let lines: Vec<Line> = matches
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(height)
+        .map(|(index, entry)| {
+            let style = if index == palette.selected {
+                app.theme.selected
+            } else {
+                Style::default()
+            };
+            Line::from(vec![
+                Span::styled(entry.label.clone(), style),
+                Span::styled(format!("  {}", entry.keys), app.theme.muted),
+            ])
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(lines), rows[2]);
+}
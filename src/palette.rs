@@ -0,0 +1,200 @@
+// The command palette: type a few letters of what you want to do and run it.
+//
+// Commands are the actions the keys are bound to, plus flipping any switch and setting any
+// parameter by name, e.g. `set delay.time 0.4`. A plain number there is the value as the effect
+// stores it; with a unit it is read like a typed-in value, e.g. `set delay.time 400ms` or
+// `set delay.time 1/8`.
+
+use crate::effect_params::Param;
+use crate::keymap::{Action, Context, Keymap};
+
+/// Something the palette can run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Action(Action),
+    /// Flips a switch.
+    Toggle(Param),
+    Set(Param, f32),
+    /// Fills in `set` and the parameter's name, for the value to be typed after it.
+    CompleteSet(Param),
+}
+
+/// A command as listed in the palette.
+pub struct Entry {
+    pub label: String,
+    /// The keys that do the same, if any.
+    pub keys: String,
+    pub command: Command,
+}
+
+#[derive(Default)]
+pub struct Palette {
+    pub query: String,
+    /// Index into the matches for the query.
+    pub selected: usize,
+    /// Why the last command couldn't run.
+    pub error: Option<String>,
+}
+
+impl Palette {
+    /// The commands matching the query, best first. Actions are those of the screen in use and
+    /// the global ones.
+    pub fn matches(&self, context: Context, keymap: &Keymap) -> Vec<Entry> {
+        let mut scored: Vec<(usize, Entry)> = entries(context, keymap)
+            .into_iter()
+            .filter_map(|entry| Some((score(&self.query, &entry.label)?, entry)))
+            .collect();
+        scored.sort_by_key(|(score, _)| *score);
+        scored.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    /// The command to run on Enter: a complete `set` command as typed, or else the selected
+    /// match. Note lengths in it are timed at `tempo`.
+    pub fn command(
+        &self,
+        context: Context,
+        keymap: &Keymap,
+        tempo: f32,
+    ) -> Result<Command, String> {
+        if let Some(command) = parse_set(&self.query, tempo) {
+            return command;
+        }
+        self.matches(context, keymap)
+            .get(self.selected)
+            .map(|entry| entry.command)
+            .ok_or_else(|| format!("no command matches {:?}", self.query))
+    }
+}
+
+fn entries(context: Context, keymap: &Keymap) -> Vec<Entry> {
+    let actions = Action::ALL
+        .iter()
+        .filter(|action| {
+            !matches!(action, Action::CommandPalette | Action::Close)
+                && (action.context() == context || action.context() == Context::Global)
+        })
+        .map(|&action| Entry {
+            label: action.description().to_string(),
+            keys: keymap
+                .keys(action)
+                .iter()
+                .map(|key| key.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            command: Command::Action(action),
+        });
+    let params = Param::ALL.into_iter().map(|param| {
+        let (label, command) = if param.is_switch() {
+            (
+                format!("toggle {}", param.name().replace('.', " ")),
+                Command::Toggle(param),
+            )
+        } else {
+            (
+                format!("set {} <value>", param.name()),
+                Command::CompleteSet(param),
+            )
+        };
+        Entry {
+            label,
+            keys: String::new(),
+            command,
+        }
+    });
+    actions.chain(params).collect()
+}
+
+/// Reads `set <parameter> <value>`. Returns `None` if the text isn't a whole `set` command yet.
+fn parse_set(query: &str, tempo: f32) -> Option<Result<Command, String>> {
+    let rest = query.trim().strip_prefix("set ")?;
+    let (name, value) = rest.trim().split_once(' ')?;
+    let Some(param) = Param::from_name(name) else {
+        return Some(Err(format!("unknown parameter {name:?}")));
+    };
+    let value = value.trim();
+    // `nan` and `inf` read as numbers, but only the unit knows what to make of them.
+    let value = match value.parse::<f32>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => param.unit().parse(value, tempo),
+    };
+    Some(value.map(|value| Command::Set(param, value)))
+}
+
+/// Whether the letters of `query` appear in order in `text`, ignoring case and spaces, and how
+/// well: the earlier and closer together they are the lower the score.
+fn score(query: &str, text: &str) -> Option<usize> {
+    let text = text.to_lowercase();
+    let mut chars = text.char_indices();
+    let mut first = None;
+    let mut last = 0;
+    let mut gaps = 0;
+    for wanted in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let (index, _) = chars.by_ref().find(|&(_, c)| c == wanted)?;
+        match first {
+            None => first = Some(index),
+            Some(_) => gaps += index - last - 1,
+        }
+        last = index;
+    }
+    Some(first.unwrap_or(0) + gaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::DEFAULT_TEMPO;
+
+    fn run(query: &str) -> Result<Command, String> {
+        let palette = Palette {
+            query: query.to_string(),
+            ..Palette::default()
+        };
+        palette.command(Context::Effects, &Keymap::default(), DEFAULT_TEMPO)
+    }
+
+    #[test]
+    fn sets_a_parameter_by_name() {
+        let Ok(Command::Set(param, value)) = run("set delay.time 400ms") else {
+            panic!("not a set command");
+        };
+        assert_eq!(param, Param::DelayTime);
+        assert!((value - 0.4).abs() < 1e-6);
+        // Without a unit it is the value as stored.
+        assert_eq!(
+            run("set delay.time 0.4"),
+            Ok(Command::Set(Param::DelayTime, 0.4))
+        );
+        // A note length is timed at the tempo.
+        assert_eq!(
+            run("set delay.time 1/8"),
+            Ok(Command::Set(Param::DelayTime, 0.25))
+        );
+    }
+
+    #[test]
+    fn rejects_values_that_are_not_numbers() {
+        assert!(run("set delay.decay nan").is_err());
+        assert!(run("set delay.time inf").is_err());
+        assert!(run("set delay.time -inf").is_err());
+        // Except where the unit has a meaning for them.
+        assert_eq!(
+            run("set distortion.level -inf"),
+            Ok(Command::Set(Param::DistortionLevel, 0.0))
+        );
+    }
+
+    #[test]
+    fn rejects_an_unknown_parameter() {
+        assert_eq!(
+            run("set delay.speed 1"),
+            Err("unknown parameter \"delay.speed\"".to_string())
+        );
+    }
+
+    #[test]
+    fn picks_the_best_match() {
+        assert_eq!(run("undo"), Ok(Command::Action(Action::Undo)));
+        assert_eq!(run("tog del"), Ok(Command::Toggle(Param::DelayBypass)));
+        assert!(run("xyzzy").is_err());
+    }
+}
@@ -1,124 +1,306 @@
-// TODO
-// The audio processing pipeline
-
+// The audio pipeline: a thread that owns the streams and keeps them running until the app quits.
+//
+// By default the input callback runs the effect chain, maps and resamples the result to the
+// output's format and pushes it into a ring buffer that the output callback plays from. The
+// buffer is prefilled with the requested latency, and between devices on separate clocks its
+// fill level steers the resampler to make up for drift. In duplex mode the input is handed over
+// within the period instead and the effects run in the output callback, for the lowest latency.
+//
+// The thread rebuilds the streams when they fail, stall or are reconfigured from the UI, and
+// moves back to the requested devices once they return after a fallback.
+//
 // Some code taken from the CPAL Feedback example
 
-use crate::EffectParams;
-use crate::effects::delay::Delay;
-use crate::effects::distortion::Distortion;
-use clap::Parser;
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crate::backend::{self, Backend, Callbacks, StreamHandle, StreamSetup};
+use crate::cli::Opt;
+use crate::drift::DriftCompensator;
+use crate::effect_params::EffectParams;
+use crate::effects::EffectChain;
+use crate::engine_state::{ActiveStreams, EngineState, StreamStatus};
+use crate::latency::LatencyController;
+use crate::logging::{self, AudioEvent, EventReceiver};
+use crate::resampler::{ChannelMapper, Resampler};
 use ringbuf::{
     HeapRb,
-    traits::{Consumer, Producer, Split},
+    traits::{Consumer, Observer, Producer, Split},
 };
 use std::sync::{
     Arc,
     atomic::{AtomicBool, Ordering},
+    mpsc::Receiver,
 };
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the pipeline thread checks on the streams.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long to wait between attempts to (re)build the streams.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+/// How long the output callback may stay silent before the streams are considered dead.
+const STALL_TIMEOUT: Duration = Duration::from_secs(2);
+/// How often the statistics window rolls over and the adaptive latency is reconsidered.
+const STATS_WINDOW: Duration = Duration::from_secs(1);
+/// The ring buffer has room for at least this much latency when it adapts automatically.
+const MAX_ADAPTIVE_LATENCY_MS: f32 = 500.0;
+/// Most frames handed to the effect chain at once. Callbacks are split into blocks of this size
+/// so the scratch buffers can be allocated up front.
+const MAX_BLOCK_FRAMES: usize = 1024;
+/// How many periods the duplex hand-off can hold.
+const DUPLEX_RING_PERIODS: usize = 4;
+
+/// Which host and devices to use and how to configure them. `None` means the default.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamSettings {
+    pub host: cpal::HostId,
+    pub input_device: Option<cpal::DeviceId>,
+    pub output_device: Option<cpal::DeviceId>,
+    pub sample_rate: Option<cpal::SampleRate>,
+    pub buffer_size: Option<cpal::FrameCount>,
+}
 
-#[derive(Parser, Debug)]
-#[command(version, about = "TUI audio effects suite", long_about = None)]
-struct Opt {
-    /// The input audio device to use
-    #[arg(short, long, value_name = "IN")]
-    input_device: Option<String>,
-
-    /// The output audio device to use
-    #[arg(short, long, value_name = "OUT")]
-    output_device: Option<String>,
-
-    /// Specify the delay between input and output
-    #[arg(short, long, value_name = "DELAY_MS", default_value_t = 150.0)]
-    latency: f32,
-
-    /// Use the JACK host
-    #[cfg(all(
-        any(
-            target_os = "linux",
-            target_os = "dragonfly",
-            target_os = "freebsd",
-            target_os = "netbsd"
-        ),
-        feature = "jack"
-    ))]
-    #[arg(short, long)]
-    #[allow(dead_code)]
-    jack: bool,
+/// Requests from the UI to the pipeline thread.
+pub enum PipelineCommand {
+    /// Switch to new stream settings, rebuilding the streams.
+    Reconfigure(StreamSettings),
 }
 
+/// Runs the pipeline on `backend` until `running` is cleared or the backend runs out of audio,
+/// which also clears `running`.
 pub fn init_pipeline(
     running: Arc<AtomicBool>,
     effect_params: Arc<EffectParams>,
+    engine: Arc<EngineState>,
+    commands: Receiver<PipelineCommand>,
+    opt: Opt,
+    mut backend: Box<dyn Backend>,
 ) -> anyhow::Result<()> {
-    let opt = Opt::parse();
-    println!("bruh");
-
-    // Conditionally compile with jack if the feature is specified.
-    #[cfg(all(
-        any(
-            target_os = "linux",
-            target_os = "dragonfly",
-            target_os = "freebsd",
-            target_os = "netbsd"
-        ),
-        feature = "jack"
-    ))]
-    // Manually check for flags. Can be passed through cargo with -- e.g.
-    // cargo run --release --example beep --features jack -- --jack
-    let host = if opt.jack {
-        cpal::host_from_id(cpal::available_hosts()
-            .into_iter()
-            .find(|id| *id == cpal::HostId::Jack)
-            .expect(
-                "make sure --features jack is specified. only works on OSes where jack is available",
-            )).expect("jack host unavailable")
-    } else {
-        cpal::default_host()
-    };
+    let mut settings = initial_settings(backend.host_id(), &opt)?;
+    log::info!("starting on host {}", settings.host.name());
+    engine.set_settings(settings.clone());
 
-    #[cfg(any(
-        not(any(
-            target_os = "linux",
-            target_os = "dragonfly",
-            target_os = "freebsd",
-            target_os = "netbsd"
-        )),
-        not(feature = "jack")
-    ))]
-    let host = cpal::default_host();
-
-    // Find devices.
-    let input_device = if let Some(device) = opt.input_device {
-        let id = &device.parse().expect("failed to parse input device id");
-        host.device_by_id(id)
-    } else {
-        host.default_input_device()
-    }
-    .expect("failed to find input device");
+    // Keep the streams running until the UI quits, rebuilding them whenever they fail. The
+    // parameters live in `effect_params`, so nothing the user set is lost on a rebuild.
+    let mut streams: Option<Streams> = None;
+    let mut next_attempt = Instant::now();
+    let mut last_heartbeat = 0;
+    let mut last_progress = Instant::now();
+    let mut next_window = Instant::now() + STATS_WINDOW;
+    // Failed attempts repeat every second, so only log an error when it changes.
+    let mut last_error = String::new();
 
-    let output_device = if let Some(device) = opt.output_device {
-        let id = &device.parse().expect("failed to parse output device id");
-        host.device_by_id(id)
-    } else {
-        host.default_output_device()
+    while running.load(Ordering::SeqCst) {
+        let now = Instant::now();
+
+        while let Ok(command) = commands.try_recv() {
+            match command {
+                PipelineCommand::Reconfigure(new_settings) => {
+                    log::info!("reconfiguring: {new_settings:?}");
+                    // Stop the current streams first, the new ones may need the same device.
+                    streams = None;
+                    settings = new_settings;
+                    engine.set_settings(settings.clone());
+                    engine.set_status(StreamStatus::Reconnecting);
+                    next_attempt = now;
+                }
+            }
+        }
+
+        if backend.finished() {
+            log::info!("backend finished, stopping");
+            running.store(false, Ordering::SeqCst);
+            break;
+        }
+
+        if let Some(active) = &mut streams {
+            if now >= next_window {
+                next_window = now + STATS_WINDOW;
+                for events in &mut active.events {
+                    events.log_pending();
+                }
+                let window = engine.stats.roll_window();
+                if let Some(latency) = &mut active.latency {
+                    let current = engine.stats.target_fill();
+                    let target = latency.update(window, current);
+                    if target != current {
+                        engine.stats.set_target_fill(target);
+                        log::info!(
+                            "adaptive latency: target now {:.0} ms",
+                            engine.stats.snapshot().target_ms
+                        );
+                    }
+                }
+            }
+
+            let heartbeat = engine.heartbeat.load(Ordering::Relaxed);
+            if heartbeat != last_heartbeat {
+                last_heartbeat = heartbeat;
+                last_progress = now;
+            }
+            let stalled = now.duration_since(last_progress) > STALL_TIMEOUT;
+            if stalled {
+                log::warn!("audio stream stopped responding");
+                engine.set_message("audio stream stopped responding");
+            }
+
+            // While on a fallback device, keep checking whether the requested ones came back.
+            let requested_returned =
+                active.fallback && now >= next_attempt && backend.devices_present(&settings);
+            if active.fallback && now >= next_attempt {
+                next_attempt = now + RETRY_INTERVAL;
+            }
+
+            let failed = engine.take_stream_failure();
+            if failed {
+                log::warn!("stream failed: {}", engine.message());
+            }
+            if requested_returned {
+                log::info!("requested devices are back");
+            }
+            if failed || stalled || requested_returned {
+                streams = None;
+                engine.set_status(StreamStatus::Reconnecting);
+                next_attempt = now;
+            }
+        }
+
+        if streams.is_none() && now >= next_attempt {
+            // Anything reported by the streams just dropped is stale now.
+            engine.take_stream_failure();
+            match start_streams(backend.as_mut(), &opt, &settings, &effect_params, &engine) {
+                Ok(started) => {
+                    if started.fallback {
+                        log::warn!("requested device missing, using the default device");
+                        engine.set_status(StreamStatus::Fallback);
+                        engine.set_message("requested device missing, using the default device");
+                    } else {
+                        log::info!("streams running");
+                        engine.set_status(StreamStatus::Running);
+                        engine.set_message("");
+                    }
+                    engine.set_active(started.active.clone());
+                    last_error.clear();
+                    last_progress = now;
+                    streams = Some(started);
+                }
+                Err(err) => {
+                    let error = format!("{err:#}");
+                    if error != last_error {
+                        log::error!("failed to start streams: {error}");
+                        last_error.clone_from(&error);
+                    }
+                    engine.set_status(StreamStatus::Reconnecting);
+                    engine.set_message(error);
+                }
+            }
+            next_attempt = now + RETRY_INTERVAL;
+        }
+
+        thread::sleep(POLL_INTERVAL);
     }
-    .expect("failed to find output device");
 
-    //println!("Using input device: \"{}\"", input_device.name()?);
-    //println!("Using output device: \"{}\"", output_device.name()?);
+    log::info!("pipeline stopped");
+    Ok(())
+}
+
+/// The stream settings asked for on the command line.
+pub fn initial_settings(host: cpal::HostId, opt: &Opt) -> anyhow::Result<StreamSettings> {
+    // Parse the requested devices up front. A malformed id is a usage error rather than
+    // something to recover from.
+    Ok(StreamSettings {
+        host,
+        input_device: parse_device_id(opt.input_device.as_deref(), "input")?,
+        output_device: parse_device_id(opt.output_device.as_deref(), "output")?,
+        sample_rate: opt.sample_rate,
+        buffer_size: opt.buffer_size,
+    })
+}
 
-    // We'll try and use the same configuration between streams to keep it simple.
-    let config: cpal::StreamConfig = input_device.default_input_config()?.into();
+/// A running pair of input and output streams. Dropping it stops them.
+struct Streams {
+    _handle: StreamHandle,
+    /// Whether a default device stands in for a requested one that is missing.
+    fallback: bool,
+    /// Sizes the safety margin when `--adaptive-latency` is on.
+    latency: Option<LatencyController>,
+    /// What the callbacks want logged.
+    events: Vec<EventReceiver>,
+    active: ActiveStreams,
+}
 
-    // Create a delay in case the input and output devices aren't synced.
-    let latency_frames = (opt.latency / 1_000.0) * config.sample_rate as f32;
-    let latency_samples = latency_frames as usize * config.channels as usize;
+fn start_streams(
+    backend: &mut dyn Backend,
+    opt: &Opt,
+    settings: &StreamSettings,
+    effect_params: &Arc<EffectParams>,
+    engine: &Arc<EngineState>,
+) -> anyhow::Result<Streams> {
+    let StreamSetup {
+        input: input_config,
+        output: output_config,
+        same_device,
+        shares_clock,
+        fallback,
+        host_name,
+        input_name,
+        output_name,
+    } = backend.configure(settings)?;
+    let active = ActiveStreams {
+        host: host_name,
+        input_device: input_name,
+        output_device: output_name,
+        sample_rate: output_config.sample_rate,
+        buffer_size: match output_config.buffer_size {
+            cpal::BufferSize::Fixed(frames) => Some(frames),
+            cpal::BufferSize::Default => None,
+        },
+    };
+    let input_channels = input_config.channels as usize;
+    let output_channels = output_config.channels as usize;
+
+    if opt.duplex {
+        if !shares_clock {
+            anyhow::bail!("duplex mode needs JACK or the same device for input and output");
+        }
+        if output_config.sample_rate != input_config.sample_rate {
+            anyhow::bail!(
+                "duplex mode needs the output at {} Hz, the input's sample rate",
+                input_config.sample_rate
+            );
+        }
+        // The hand-off is sized in periods, so the period has to be known up front.
+        let cpal::BufferSize::Fixed(period) = output_config.buffer_size else {
+            anyhow::bail!("duplex mode needs a fixed buffer size");
+        };
+        let (handle, events) = start_duplex_streams(
+            backend,
+            &input_config,
+            &output_config,
+            period,
+            effect_params,
+            engine,
+        )?;
+        return Ok(Streams {
+            _handle: handle,
+            fallback,
+            latency: None,
+            events,
+            active,
+        });
+    }
 
-    //println!("{}", config.sample_rate.0 as usize);
+    // Create a delay in case the input and output devices aren't synced.
+    let latency_frames = (opt.latency / 1_000.0) * output_config.sample_rate as f32;
+    // A latency too short to hold one frame still needs a ring buffer with room in it.
+    let latency_samples = (latency_frames as usize).max(1) * output_channels;
 
-    // The buffer to share samples
-    let ring = HeapRb::<f32>::new(latency_samples * 2);
+    // The buffer to share samples. Adapting the latency needs room to grow into.
+    let capacity = if opt.adaptive_latency {
+        let max_frames = (MAX_ADAPTIVE_LATENCY_MS / 1_000.0) * output_config.sample_rate as f32;
+        latency_samples.max(max_frames as usize * output_channels) * 2
+    } else {
+        latency_samples * 2
+    };
+    let ring = HeapRb::<f32>::new(capacity);
     let (mut producer, mut consumer) = ring.split();
 
     // Fill the samples with 0.0 equal to the length of the delay.
@@ -127,30 +309,102 @@ pub fn init_pipeline(
         // so this should never fail
         producer.try_push(0.0).unwrap();
     }
+    engine.stats.start(
+        capacity,
+        latency_samples,
+        output_channels,
+        output_config.sample_rate,
+        opt.adaptive_latency,
+    );
 
-    let distortion = Distortion::new(Arc::clone(&effect_params));
-    let mut delay = Delay::new(
-        config.sample_rate as f32 * config.channels as f32,
-        Arc::clone(&effect_params),
+    let mut effects = EffectChain::new(
+        input_config.sample_rate,
+        input_channels,
+        Arc::clone(effect_params),
     );
 
-    let input_data_fn = move |data: &[f32], _: &cpal::InputCallbackInfo| {
+    // Separate devices run on separate clocks, so keep the resampler adjustable and let the
+    // drift compensator hold the ring buffer at its prefill level.
+    let compensate_drift = !opt.no_drift_compensation && !same_device;
+    let mut drift = DriftCompensator::new(latency_samples);
+
+    // The effects run in the input stream's format, then get mapped and resampled to the output
+    // stream's format before going into the ring buffer.
+    let channel_mapper = ChannelMapper::new(input_channels, output_channels);
+    let mut resampler = Resampler::new(
+        input_config.sample_rate,
+        output_config.sample_rate,
+        output_channels,
+        compensate_drift,
+    )?;
+    // Scratch space, so the callback never allocates.
+    let mut block = vec![0.0; MAX_BLOCK_FRAMES * input_channels];
+    let mut mapped = vec![0.0; output_channels];
+
+    let (mut input_events, input_receiver) = logging::event_queue();
+    let input_engine = Arc::clone(engine);
+    let input_rate = input_config.sample_rate as f32;
+    let input_data_fn = move |data: &[f32]| {
+        let started = Instant::now();
         let mut output_fell_behind = false;
-        for &sample in data {
-            let sample = distortion.process(sample);
-            let sample = delay.process(sample);
-            if producer.try_push(sample).is_err() {
-                output_fell_behind = true;
+        for input in data.chunks(block.len()) {
+            let block = &mut block[..input.len()];
+            block.copy_from_slice(input);
+            effects.process_block(block);
+            for frame in block.chunks_exact(input_channels) {
+                channel_mapper.map(frame, &mut mapped);
+                resampler.process(&mapped, |sample| {
+                    if producer.try_push(sample).is_err() {
+                        output_fell_behind = true;
+                    }
+                });
             }
         }
         if output_fell_behind {
-            eprintln!("output stream fell behind: try increasing latency");
+            input_engine.stats.record_overrun();
+            input_events.send(AudioEvent::Overrun);
         }
+        if compensate_drift {
+            drift.set_target(input_engine.stats.target_fill());
+            resampler.set_ratio_relative(drift.update(producer.occupied_len()));
+        }
+
+        let frames = data.len() / input_channels;
+        input_engine.stats.record_callback(
+            started.elapsed(),
+            Duration::from_secs_f32(frames as f32 / input_rate),
+        );
     };
 
-    let output_data_fn = move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+    let (mut output_events, output_receiver) = logging::event_queue();
+    let output_engine = Arc::clone(engine);
+    let mut current_target = latency_samples;
+    let mut pending_silence = 0;
+    let output_data_fn = move |data: &mut [f32]| {
+        let stats = &output_engine.stats;
+        output_engine.heartbeat.fetch_add(1, Ordering::Relaxed);
+        stats.record_fill(consumer.occupied_len(), data.len());
+
+        // Follow the adaptive latency: growing inserts silence, shrinking drops samples.
+        let target = stats.target_fill();
+        if target > current_target {
+            pending_silence += target - current_target;
+        } else if target < current_target {
+            // Silence not played yet goes first.
+            let excess = current_target - target;
+            let unplayed = excess.min(pending_silence);
+            pending_silence -= unplayed;
+            consumer.skip(excess - unplayed);
+        }
+        current_target = target;
+
         let mut input_fell_behind = false;
         for sample in data {
+            if pending_silence > 0 {
+                pending_silence -= 1;
+                *sample = 0.0;
+                continue;
+            }
             *sample = match consumer.try_pop() {
                 Some(s) => s,
                 None => {
@@ -160,34 +414,137 @@ pub fn init_pipeline(
             };
         }
         if input_fell_behind {
-            eprintln!("input stream fell behind: try increasing latency");
+            stats.record_underrun();
+            output_events.send(AudioEvent::Underrun);
         }
     };
 
-    // Build streams.
-    //println!("Attempting to build both streams with f32 samples and `{config:?}`.");
-    let input_stream = input_device.build_input_stream(&config, input_data_fn, err_fn, None)?;
-    let output_stream = output_device.build_output_stream(&config, output_data_fn, err_fn, None)?;
-    //println!("Successfully built streams.");
-
-    // Play the streams.
-    //println!(
-    //    "Starting the input and output streams with `{}` milliseconds of latency.",
-    //    opt.latency
-    //);
-    input_stream.play()?;
-    output_stream.play()?;
-
-    while running.load(Ordering::SeqCst) {}
-
-    // Run for 3 seconds before closing.
-    //println!("Playing for 3 seconds... ");
-    drop(input_stream);
-    drop(output_stream);
-    //println!("Done!");
-    Ok(())
+    log::debug!("starting streams with {} ms of latency", opt.latency);
+    let handle = backend.start(Callbacks {
+        input: Box::new(input_data_fn),
+        output: Box::new(output_data_fn),
+        error: err_fn(engine),
+    })?;
+
+    Ok(Streams {
+        _handle: handle,
+        fallback,
+        latency: opt
+            .adaptive_latency
+            .then(|| LatencyController::new(capacity * 3 / 4)),
+        events: vec![input_receiver, output_receiver],
+        active,
+    })
+}
+
+/// Runs the effect chain in the output callback on input the input callback handed over during
+/// the same period, so the only latency added is the one period the output waits to be primed.
+/// Only sound when both streams run off one clock at the same rate, `period` frames at a time.
+fn start_duplex_streams(
+    backend: &mut dyn Backend,
+    input_config: &cpal::StreamConfig,
+    output_config: &cpal::StreamConfig,
+    period: cpal::FrameCount,
+    effect_params: &Arc<EffectParams>,
+    engine: &Arc<EngineState>,
+) -> anyhow::Result<(StreamHandle, Vec<EventReceiver>)> {
+    let input_channels = input_config.channels as usize;
+    let output_channels = output_config.channels as usize;
+
+    // Room for a few periods, so the input can run ahead while the output is being primed.
+    let ring = HeapRb::<f32>::new(period as usize * DUPLEX_RING_PERIODS * input_channels);
+    let capacity = ring.capacity().get();
+    let (mut producer, mut consumer) = ring.split();
+    engine
+        .stats
+        .start(capacity, 0, input_channels, input_config.sample_rate, false);
+
+    let (mut input_events, input_receiver) = logging::event_queue();
+    let input_engine = Arc::clone(engine);
+    let input_data_fn = move |data: &[f32]| {
+        if producer.push_slice(data) < data.len() {
+            input_engine.stats.record_overrun();
+            input_events.send(AudioEvent::Overrun);
+        }
+    };
+
+    let mut effects = EffectChain::new(
+        input_config.sample_rate,
+        input_channels,
+        Arc::clone(effect_params),
+    );
+    let channel_mapper = ChannelMapper::new(input_channels, output_channels);
+    let mut block = vec![0.0; MAX_BLOCK_FRAMES * input_channels];
+    let mut primed = false;
+
+    let (mut output_events, output_receiver) = logging::event_queue();
+    let output_engine = Arc::clone(engine);
+    let sample_rate = input_config.sample_rate as f32;
+    let output_data_fn = move |data: &mut [f32]| {
+        let started = Instant::now();
+        let stats = &output_engine.stats;
+        output_engine.heartbeat.fetch_add(1, Ordering::Relaxed);
+        let frames = data.len() / output_channels;
+        let needed = frames * input_channels;
+
+        // Wait until a whole period of input is there before playing it. Whatever piled up
+        // beyond that is dropped so the latency stays at one period.
+        let available = consumer.occupied_len();
+        stats.record_fill(available, needed);
+        if !primed {
+            primed = available >= needed;
+        } else if available > needed * 2 {
+            consumer.skip(available - needed);
+        }
+        if !primed {
+            data.fill(0.0);
+            return;
+        }
+
+        for out in data.chunks_mut(MAX_BLOCK_FRAMES * output_channels) {
+            let block = &mut block[..out.len() / output_channels * input_channels];
+            let popped = consumer.pop_slice(block);
+            if popped < block.len() {
+                // Start priming again rather than playing a gap every period.
+                primed = false;
+                block[popped..].fill(0.0);
+            }
+            effects.process_block(block);
+            for (out, frame) in out
+                .chunks_exact_mut(output_channels)
+                .zip(block.chunks_exact(input_channels))
+            {
+                channel_mapper.map(frame, out);
+            }
+        }
+        if !primed {
+            stats.record_underrun();
+            output_events.send(AudioEvent::Underrun);
+        }
+        stats.record_callback(
+            started.elapsed(),
+            Duration::from_secs_f32(frames as f32 / sample_rate),
+        );
+    };
+
+    let handle = backend.start(Callbacks {
+        input: Box::new(input_data_fn),
+        output: Box::new(output_data_fn),
+        error: err_fn(engine),
+    })?;
+    Ok((handle, vec![input_receiver, output_receiver]))
+}
+
+fn parse_device_id(id: Option<&str>, kind: &str) -> anyhow::Result<Option<cpal::DeviceId>> {
+    id.map(|id| {
+        id.parse()
+            .map_err(|err| anyhow::anyhow!("failed to parse {kind} device id: {err}"))
+    })
+    .transpose()
 }
 
-fn err_fn(err: cpal::StreamError) {
-    eprintln!("an error occurred on stream: {err}");
+/// Flags the streams for a rebuild when they fail.
+fn err_fn(engine: &Arc<EngineState>) -> backend::ErrorCallback {
+    let engine = Arc::clone(engine);
+    Box::new(move |err| engine.report_stream_error(&err))
 }
@@ -0,0 +1,164 @@
+// Sample rate and channel conversion between the input and output streams.
+
+use rubato::{
+    Resampler as _, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction,
+};
+
+/// Frames collected before each pass through the sinc resampler. Small enough to keep the added
+/// latency negligible next to the ring buffer.
+const CHUNK_FRAMES: usize = 64;
+
+/// Maps interleaved frames from the input stream's channel layout to the output stream's.
+pub struct ChannelMapper {
+    input_channels: usize,
+    output_channels: usize,
+}
+
+impl ChannelMapper {
+    pub fn new(input_channels: usize, output_channels: usize) -> Self {
+        Self {
+            input_channels,
+            output_channels,
+        }
+    }
+
+    /// Maps a single frame. Mono is duplicated to every output channel, anything downmixed to
+    /// mono is averaged, and otherwise channels are matched up by index, wrapping around the
+    /// input channels when the output has more.
+    pub fn map(&self, input: &[f32], output: &mut [f32]) {
+        if self.output_channels == 1 {
+            output[0] = input.iter().sum::<f32>() / self.input_channels as f32;
+            return;
+        }
+        for (channel, sample) in output.iter_mut().enumerate() {
+            *sample = input[channel % self.input_channels];
+        }
+    }
+}
+
+/// Converts interleaved audio from one sample rate to another with a windowed sinc resampler.
+/// Audio is passed straight through when the rates already match, unless the ratio has to stay
+/// adjustable for drift compensation.
+pub struct Resampler {
+    channels: usize,
+    inner: Option<SincFixedIn<f32>>,
+    input: Vec<Vec<f32>>,
+    output: Vec<Vec<f32>>,
+}
+
+impl Resampler {
+    pub fn new(
+        input_rate: u32,
+        output_rate: u32,
+        channels: usize,
+        adjustable: bool,
+    ) -> anyhow::Result<Self> {
+        if input_rate == output_rate && !adjustable {
+            return Ok(Self {
+                channels,
+                inner: None,
+                input: Vec::new(),
+                output: Vec::new(),
+            });
+        }
+
+        let sinc_len = 128;
+        let window = WindowFunction::BlackmanHarris2;
+        let parameters = SincInterpolationParameters {
+            sinc_len,
+            f_cutoff: rubato::calculate_cutoff(sinc_len, window),
+            oversampling_factor: 256,
+            interpolation: SincInterpolationType::Cubic,
+            window,
+        };
+        let inner = SincFixedIn::<f32>::new(
+            output_rate as f64 / input_rate as f64,
+            1.1,
+            parameters,
+            CHUNK_FRAMES,
+            channels,
+        )?;
+        let output = inner.output_buffer_allocate(true);
+
+        Ok(Self {
+            channels,
+            inner: Some(inner),
+            input: vec![Vec::with_capacity(CHUNK_FRAMES); channels],
+            output,
+        })
+    }
+
+    /// Scales the conversion ratio by `ratio`, ramping to it over the next chunk. Does nothing
+    /// for a pass-through resampler.
+    pub fn set_ratio_relative(&mut self, ratio: f64) {
+        if let Some(inner) = &mut self.inner {
+            // Only fails outside the range given to the constructor, which the drift
+            // compensator never leaves.
+            let _ = inner.set_resample_ratio_relative(ratio, true);
+        }
+    }
+
+    /// How many output frames the resampler holds back, counting the chunk it collects before
+    /// each pass and the sinc filter's own delay.
+    pub fn latency_frames(&self) -> usize {
+        self.inner
+            .as_ref()
+            .map_or(0, |inner| inner.output_frames_next() + inner.output_delay())
+    }
+
+    /// Feeds interleaved `input` through the resampler, calling `emit` for every interleaved
+    /// output sample that becomes available.
+    pub fn process(&mut self, input: &[f32], mut emit: impl FnMut(f32)) {
+        let Some(inner) = &mut self.inner else {
+            input.iter().for_each(|&sample| emit(sample));
+            return;
+        };
+
+        for frame in input.chunks_exact(self.channels) {
+            for (channel, &sample) in self.input.iter_mut().zip(frame) {
+                channel.push(sample);
+            }
+            if self.input[0].len() < inner.input_frames_next() {
+                continue;
+            }
+
+            // The buffers are sized by the resampler itself, so this can't fail.
+            if let Ok((_, written)) = inner.process_into_buffer(&self.input, &mut self.output, None)
+            {
+                for frame in 0..written {
+                    for channel in &self.output {
+                        emit(channel[frame]);
+                    }
+                }
+            }
+            for channel in &mut self.input {
+                channel.clear();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_no_alloc::assert_no_alloc;
+
+    #[test]
+    fn conversion_runs_without_allocating() {
+        let mapper = ChannelMapper::new(2, 1);
+        let mut resampler = Resampler::new(48_000, 44_100, 1, true).unwrap();
+        let input: Vec<f32> = (0..4096).map(|i| (i as f32 * 0.01).sin()).collect();
+        let mut mapped = [0.0];
+        let mut emitted = 0;
+
+        // The first pass fills the resampler's buffers, so it has to happen under the guard too.
+        assert_no_alloc(|| {
+            for frame in input.chunks_exact(2) {
+                mapper.map(frame, &mut mapped);
+                resampler.process(&mapped, |_| emitted += 1);
+                resampler.set_ratio_relative(1.001);
+            }
+        });
+        assert!(emitted > 0);
+    }
+}
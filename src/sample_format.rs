@@ -0,0 +1,246 @@
+// Sample format negotiation and conversion at the stream boundary.
+//
+// The effect chain only ever sees f32 samples. Devices that only expose integer (or f64) formats
+// get their samples converted on the way in and out of the callbacks.
+//
+// The callbacks run under `assert_no_alloc`, so debug builds abort on any allocation in the audio
+// path. Conversion goes through a scratch buffer allocated up front, a chunk at a time.
+
+use assert_no_alloc::assert_no_alloc;
+use cpal::traits::DeviceTrait;
+use cpal::{FromSample, SampleFormat, SizedSample, StreamConfig};
+
+/// Formats we know how to convert, in order of preference.
+const PREFERRED_FORMATS: [SampleFormat; 12] = [
+    SampleFormat::F32,
+    SampleFormat::F64,
+    SampleFormat::I32,
+    SampleFormat::I24,
+    SampleFormat::I16,
+    SampleFormat::U32,
+    SampleFormat::U24,
+    SampleFormat::U16,
+    SampleFormat::I64,
+    SampleFormat::U64,
+    SampleFormat::I8,
+    SampleFormat::U8,
+];
+
+/// Most samples converted in one go. Larger callbacks are handed to `data_fn` in chunks.
+const CONVERT_CHUNK: usize = 4096;
+
+/// Picks the best sample format the input device supports for `config`.
+pub fn input_format(device: &cpal::Device, config: &StreamConfig) -> anyhow::Result<SampleFormat> {
+    let formats = device
+        .supported_input_configs()?
+        .filter(|range| supports(range, config))
+        .map(|range| range.sample_format());
+    pick_format(formats)
+}
+
+/// Picks the best sample format the output device supports for `config`.
+pub fn output_format(device: &cpal::Device, config: &StreamConfig) -> anyhow::Result<SampleFormat> {
+    let formats = device
+        .supported_output_configs()?
+        .filter(|range| supports(range, config))
+        .map(|range| range.sample_format());
+    pick_format(formats)
+}
+
+fn supports(range: &cpal::SupportedStreamConfigRange, config: &StreamConfig) -> bool {
+    range.channels() == config.channels
+        && range.min_sample_rate() <= config.sample_rate
+        && config.sample_rate <= range.max_sample_rate()
+}
+
+fn pick_format(formats: impl Iterator<Item = SampleFormat>) -> anyhow::Result<SampleFormat> {
+    let formats: Vec<SampleFormat> = formats.collect();
+    PREFERRED_FORMATS
+        .into_iter()
+        .find(|format| formats.contains(format))
+        .ok_or_else(|| anyhow::anyhow!("no supported sample format among {formats:?}"))
+}
+
+/// Builds an input stream in `format`, handing `data_fn` the samples converted to f32.
+pub fn build_input_stream<D, E>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    format: SampleFormat,
+    mut data_fn: D,
+    err_fn: E,
+) -> anyhow::Result<cpal::Stream>
+where
+    D: FnMut(&[f32]) + Send + 'static,
+    E: FnMut(cpal::StreamError) + Send + 'static,
+{
+    match format {
+        SampleFormat::F32 => Ok(device.build_input_stream(
+            config,
+            move |data: &[f32], _: &cpal::InputCallbackInfo| assert_no_alloc(|| data_fn(data)),
+            err_fn,
+            None,
+        )?),
+        SampleFormat::F64 => typed_input_stream::<f64, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::I8 => typed_input_stream::<i8, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::I16 => typed_input_stream::<i16, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::I24 => typed_input_stream::<cpal::I24, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::I32 => typed_input_stream::<i32, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::I64 => typed_input_stream::<i64, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::U8 => typed_input_stream::<u8, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::U16 => typed_input_stream::<u16, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::U24 => typed_input_stream::<cpal::U24, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::U32 => typed_input_stream::<u32, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::U64 => typed_input_stream::<u64, _, _>(device, config, data_fn, err_fn),
+        format => anyhow::bail!("unsupported input sample format: {format}"),
+    }
+}
+
+/// Builds an output stream in `format`, letting `data_fn` fill an f32 buffer that is then
+/// converted to the device format.
+pub fn build_output_stream<D, E>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    format: SampleFormat,
+    mut data_fn: D,
+    err_fn: E,
+) -> anyhow::Result<cpal::Stream>
+where
+    D: FnMut(&mut [f32]) + Send + 'static,
+    E: FnMut(cpal::StreamError) + Send + 'static,
+{
+    match format {
+        SampleFormat::F32 => Ok(device.build_output_stream(
+            config,
+            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| assert_no_alloc(|| data_fn(data)),
+            err_fn,
+            None,
+        )?),
+        SampleFormat::F64 => typed_output_stream::<f64, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::I8 => typed_output_stream::<i8, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::I16 => typed_output_stream::<i16, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::I24 => {
+            typed_output_stream::<cpal::I24, _, _>(device, config, data_fn, err_fn)
+        }
+        SampleFormat::I32 => typed_output_stream::<i32, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::I64 => typed_output_stream::<i64, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::U8 => typed_output_stream::<u8, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::U16 => typed_output_stream::<u16, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::U24 => {
+            typed_output_stream::<cpal::U24, _, _>(device, config, data_fn, err_fn)
+        }
+        SampleFormat::U32 => typed_output_stream::<u32, _, _>(device, config, data_fn, err_fn),
+        SampleFormat::U64 => typed_output_stream::<u64, _, _>(device, config, data_fn, err_fn),
+        format => anyhow::bail!("unsupported output sample format: {format}"),
+    }
+}
+
+fn typed_input_stream<T, D, E>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    mut data_fn: D,
+    err_fn: E,
+) -> anyhow::Result<cpal::Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+    D: FnMut(&[f32]) + Send + 'static,
+    E: FnMut(cpal::StreamError) + Send + 'static,
+{
+    let mut converted = vec![0.0; chunk_len(config)];
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            assert_no_alloc(|| {
+                for chunk in data.chunks(converted.len()) {
+                    let converted = &mut converted[..chunk.len()];
+                    for (out, &sample) in converted.iter_mut().zip(chunk) {
+                        *out = sample.to_sample::<f32>();
+                    }
+                    data_fn(converted);
+                }
+            })
+        },
+        err_fn,
+        None,
+    )?;
+    Ok(stream)
+}
+
+fn typed_output_stream<T, D, E>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    mut data_fn: D,
+    err_fn: E,
+) -> anyhow::Result<cpal::Stream>
+where
+    T: SizedSample + FromSample<f32>,
+    D: FnMut(&mut [f32]) + Send + 'static,
+    E: FnMut(cpal::StreamError) + Send + 'static,
+{
+    let mut converted = vec![0.0; chunk_len(config)];
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            assert_no_alloc(|| {
+                for chunk in data.chunks_mut(converted.len()) {
+                    let converted = &mut converted[..chunk.len()];
+                    data_fn(converted);
+                    for (out, &sample) in chunk.iter_mut().zip(converted.iter()) {
+                        *out = T::from_sample(sample);
+                    }
+                }
+            })
+        },
+        err_fn,
+        None,
+    )?;
+    Ok(stream)
+}
+
+/// Size of the conversion scratch buffer: as many whole frames as fit in `CONVERT_CHUNK`.
+fn chunk_len(config: &StreamConfig) -> usize {
+    let channels = config.channels.max(1) as usize;
+    (CONVERT_CHUNK / channels).max(1) * channels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_most_preferred_format() {
+        let pick = |formats: &[SampleFormat]| pick_format(formats.iter().copied()).unwrap();
+        assert_eq!(
+            pick(&[SampleFormat::I16, SampleFormat::F32, SampleFormat::I32]),
+            SampleFormat::F32
+        );
+        assert_eq!(
+            pick(&[SampleFormat::U8, SampleFormat::I16, SampleFormat::I24]),
+            SampleFormat::I24
+        );
+        assert_eq!(
+            pick(&[SampleFormat::I8, SampleFormat::U16]),
+            SampleFormat::U16
+        );
+    }
+
+    #[test]
+    fn fails_without_a_format_to_convert() {
+        assert!(pick_format(std::iter::empty()).is_err());
+        assert!(pick_format([SampleFormat::DsdU8, SampleFormat::DsdU32].into_iter()).is_err());
+    }
+
+    #[test]
+    fn conversion_chunks_hold_whole_frames() {
+        let config = |channels| StreamConfig {
+            channels,
+            sample_rate: 48_000,
+            buffer_size: cpal::BufferSize::Default,
+        };
+        assert_eq!(chunk_len(&config(2)), CONVERT_CHUNK);
+        assert_eq!(chunk_len(&config(6)), 4092);
+        assert_eq!(chunk_len(&config(0)), CONVERT_CHUNK);
+        // A frame wider than a chunk still gets converted whole.
+        assert_eq!(chunk_len(&config(5000)), 5000);
+    }
+}
@@ -0,0 +1,254 @@
+// State behind the audio settings screen: which host, devices, sample rate and buffer size are
+// picked, and what there is to pick from.
+
+use crate::devices::{self, DeviceInfo};
+use crate::pipeline::StreamSettings;
+use cpal::traits::{DeviceTrait, HostTrait};
+
+/// The rows of the settings screen, top to bottom.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingsField {
+    Host,
+    InputDevice,
+    OutputDevice,
+    SampleRate,
+    BufferSize,
+}
+
+pub const FIELDS: [SettingsField; 5] = [
+    SettingsField::Host,
+    SettingsField::InputDevice,
+    SettingsField::OutputDevice,
+    SettingsField::SampleRate,
+    SettingsField::BufferSize,
+];
+
+#[derive(Default)]
+pub struct SettingsState {
+    pub field: usize,
+    pub hosts: Vec<cpal::HostId>,
+    pub host: usize,
+    pub devices: Vec<DeviceInfo>,
+    /// Indices into `devices` of the ones that can record or play.
+    pub inputs: Vec<usize>,
+    pub outputs: Vec<usize>,
+    /// Index into `inputs`/`outputs` plus one, with 0 meaning the host's default device.
+    pub input: usize,
+    pub output: usize,
+    default_input: Option<cpal::DeviceId>,
+    default_output: Option<cpal::DeviceId>,
+    pub sample_rates: Vec<u32>,
+    /// Index into `sample_rates` plus one, with 0 meaning the device's default.
+    pub sample_rate: usize,
+    pub buffer_sizes: Vec<u32>,
+    /// Index into `buffer_sizes` plus one, with 0 meaning the host's default.
+    pub buffer_size: usize,
+    pub error: Option<String>,
+}
+
+impl SettingsState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Re-enumerates everything and selects whatever `current` uses.
+    pub fn load(&mut self, current: Option<&StreamSettings>) {
+        self.hosts = cpal::available_hosts();
+        self.host = current
+            .and_then(|settings| self.hosts.iter().position(|&id| id == settings.host))
+            .unwrap_or(0);
+        self.load_devices(current);
+    }
+
+    /// Enumerates the devices of the selected host.
+    fn load_devices(&mut self, current: Option<&StreamSettings>) {
+        self.devices.clear();
+        self.default_input = None;
+        self.default_output = None;
+        self.error = None;
+        match self.hosts.get(self.host).map(|&id| cpal::host_from_id(id)) {
+            Some(Ok(host)) => {
+                match devices::list_devices(&host) {
+                    Ok(devices) => self.devices = devices,
+                    Err(err) => self.error = Some(err.to_string()),
+                }
+                self.default_input = host.default_input_device().and_then(|d| d.id().ok());
+                self.default_output = host.default_output_device().and_then(|d| d.id().ok());
+            }
+            Some(Err(err)) => self.error = Some(err.to_string()),
+            None => {}
+        }
+        self.update_device_lists(current);
+    }
+
+    fn update_device_lists(&mut self, current: Option<&StreamSettings>) {
+        self.inputs = (0..self.devices.len())
+            .filter(|&i| self.devices[i].is_input())
+            .collect();
+        self.outputs = (0..self.devices.len())
+            .filter(|&i| self.devices[i].is_output())
+            .collect();
+        self.input = current
+            .and_then(|settings| settings.input_device.as_ref())
+            .and_then(|id| self.position(&self.inputs, id))
+            .unwrap_or(0);
+        self.output = current
+            .and_then(|settings| settings.output_device.as_ref())
+            .and_then(|id| self.position(&self.outputs, id))
+            .unwrap_or(0);
+        self.update_stream_options(current);
+    }
+
+    fn position(&self, list: &[usize], id: &cpal::DeviceId) -> Option<usize> {
+        list.iter()
+            .position(|&i| self.devices[i].id == *id)
+            .map(|i| i + 1)
+    }
+
+    /// Offers the sample rates and buffer sizes the selected input device supports.
+    fn update_stream_options(&mut self, current: Option<&StreamSettings>) {
+        let configs = self
+            .input_device()
+            .map(|device| device.input_configs.as_slice())
+            .unwrap_or_default();
+        let (sample_rates, buffer_sizes) = (
+            devices::supported_sample_rates(configs),
+            devices::supported_buffer_sizes(configs),
+        );
+        self.sample_rates = sample_rates;
+        self.buffer_sizes = buffer_sizes;
+        self.sample_rate = current
+            .and_then(|settings| settings.sample_rate)
+            .and_then(|rate| self.sample_rates.iter().position(|&r| r == rate))
+            .map_or(0, |i| i + 1);
+        self.buffer_size = current
+            .and_then(|settings| settings.buffer_size)
+            .and_then(|frames| self.buffer_sizes.iter().position(|&f| f == frames))
+            .map_or(0, |i| i + 1);
+    }
+
+    /// The selected input device, resolving "default" to the host's default device.
+    pub fn input_device(&self) -> Option<&DeviceInfo> {
+        self.selected_device(&self.inputs, self.input, self.default_input.as_ref())
+    }
+
+    /// The selected output device, resolving "default" to the host's default device.
+    pub fn output_device(&self) -> Option<&DeviceInfo> {
+        self.selected_device(&self.outputs, self.output, self.default_output.as_ref())
+    }
+
+    fn selected_device(
+        &self,
+        list: &[usize],
+        selection: usize,
+        default: Option<&cpal::DeviceId>,
+    ) -> Option<&DeviceInfo> {
+        match selection {
+            0 => default.and_then(|id| self.devices.iter().find(|device| device.id == *id)),
+            n => list.get(n - 1).map(|&i| &self.devices[i]),
+        }
+    }
+
+    pub fn selected_field(&self) -> SettingsField {
+        FIELDS[self.field]
+    }
+
+    pub fn next_field(&mut self) {
+        self.field = (self.field + 1) % FIELDS.len();
+    }
+
+    pub fn previous_field(&mut self) {
+        self.field = (self.field + FIELDS.len() - 1) % FIELDS.len();
+    }
+
+    /// Steps the value of the selected field forwards or backwards, wrapping around.
+    pub fn change(&mut self, forward: bool) {
+        let step = |value: usize, len: usize| {
+            if len == 0 {
+                0
+            } else if forward {
+                (value + 1) % len
+            } else {
+                (value + len - 1) % len
+            }
+        };
+        match self.selected_field() {
+            SettingsField::Host => {
+                self.host = step(self.host, self.hosts.len());
+                self.load_devices(None);
+            }
+            SettingsField::InputDevice => {
+                self.input = step(self.input, self.inputs.len() + 1);
+                self.update_stream_options(None);
+            }
+            SettingsField::OutputDevice => {
+                self.output = step(self.output, self.outputs.len() + 1);
+            }
+            SettingsField::SampleRate => {
+                self.sample_rate = step(self.sample_rate, self.sample_rates.len() + 1);
+            }
+            SettingsField::BufferSize => {
+                self.buffer_size = step(self.buffer_size, self.buffer_sizes.len() + 1);
+            }
+        }
+    }
+
+    /// The settings as currently picked, or `None` if there is no host to pick from.
+    pub fn selected(&self) -> Option<StreamSettings> {
+        let device_id = |list: &[usize], selection: usize| match selection {
+            0 => None,
+            n => list.get(n - 1).map(|&i| self.devices[i].id.clone()),
+        };
+        Some(StreamSettings {
+            host: *self.hosts.get(self.host)?,
+            input_device: device_id(&self.inputs, self.input),
+            output_device: device_id(&self.outputs, self.output),
+            sample_rate: self
+                .sample_rate
+                .checked_sub(1)
+                .map(|i| self.sample_rates[i]),
+            buffer_size: self
+                .buffer_size
+                .checked_sub(1)
+                .map(|i| self.buffer_sizes[i]),
+        })
+    }
+
+    /// Text for the value of `field`.
+    pub fn value_label(&self, field: SettingsField) -> String {
+        let device_label = |list: &[usize], selection: usize| match selection {
+            0 => "Default".to_string(),
+            n => list
+                .get(n - 1)
+                .map_or_else(String::new, |&i| self.devices[i].name.clone()),
+        };
+        match field {
+            SettingsField::Host => self
+                .hosts
+                .get(self.host)
+                .map_or_else(|| "none".to_string(), |id| id.name().to_string()),
+            SettingsField::InputDevice => device_label(&self.inputs, self.input),
+            SettingsField::OutputDevice => device_label(&self.outputs, self.output),
+            SettingsField::SampleRate => match self.sample_rate {
+                0 => "Default".to_string(),
+                n => format!("{} Hz", self.sample_rates[n - 1]),
+            },
+            SettingsField::BufferSize => match self.buffer_size {
+                0 => "Default".to_string(),
+                n => format!("{} frames", self.buffer_sizes[n - 1]),
+            },
+        }
+    }
+}
+
+impl SettingsField {
+    pub fn label(self) -> &'static str {
+        match self {
+            SettingsField::Host => "Host",
+            SettingsField::InputDevice => "Input device",
+            SettingsField::OutputDevice => "Output device",
+            SettingsField::SampleRate => "Sample rate",
+            SettingsField::BufferSize => "Buffer size",
+        }
+    }
+}
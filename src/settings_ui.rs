@@ -0,0 +1,122 @@
+use crate::app::App;
+use crate::devices;
+use crate::keymap::{Action, Keymap};
+use crate::settings::FIELDS;
+use crate::theme::Theme;
+use ratatui::{
+    Frame,
+    layout::{
+        Constraint::{Length, Min, Percentage},
+        Layout, Rect,
+    },
+    style::Style,
+    text::{Line, Span},
+    widgets::{Block, Paragraph},
+};
+
+pub fn draw_settings(frame: &mut Frame, app: &mut App, area: Rect) {
+    let block = Block::bordered().title(Span::styled("Audio settings", app.theme.title));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let rows = Layout::vertical([Length(FIELDS.len() as u16 + 1), Min(0), Length(1)]).split(inner);
+    let settings = &app.settings;
+
+    let fields: Vec<Line> = FIELDS
+        .iter()
+        .enumerate()
+        .map(|(i, &field)| {
+            let mut value_style = Style::default();
+            if i == settings.field {
+                value_style = app.theme.selected;
+            }
+            Line::from(vec![
+                Span::raw(format!(" {:<14}", field.label())),
+                Span::styled(format!("◀ {} ▶", settings.value_label(field)), value_style),
+            ])
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(fields), rows[0]);
+
+    let columns = Layout::horizontal([Percentage(50), Percentage(50)]).split(rows[1]);
+    draw_configs(
+        frame,
+        "Input configs",
+        settings
+            .input_device()
+            .map(|device| (&device.default_input, &device.input_configs)),
+        columns[0],
+        &app.theme,
+    );
+    draw_configs(
+        frame,
+        "Output configs",
+        settings
+            .output_device()
+            .map(|device| (&device.default_output, &device.output_configs)),
+        columns[1],
+        &app.theme,
+    );
+
+    let footer = match &settings.error {
+        Some(error) => Span::styled(format!(" {error}"), app.theme.error),
+        None => Span::styled(key_hints(&app.keymap), app.theme.muted),
+    };
+    frame.render_widget(Line::from(footer), rows[2]);
+}
+
+/// The keys for the settings, as bound in the keymap, e.g. ` Up/Down select  Enter apply`.
+fn key_hints(keymap: &Keymap) -> String {
+    let hints: [(&[Action], &str); 4] = [
+        (&[Action::PreviousField, Action::NextField], "select"),
+        (&[Action::PreviousValue, Action::NextValue], "change"),
+        (&[Action::ApplySettings], "apply"),
+        (&[Action::ReloadSettings], "refresh"),
+    ];
+    let mut text = String::new();
+    for (actions, what) in hints {
+        let keys: Vec<String> = actions
+            .iter()
+            .flat_map(|&action| keymap.keys(action))
+            .map(|key| key.to_string())
+            .collect();
+        if !keys.is_empty() {
+            text.push_str(&format!(" {} {what} ", keys.join("/")));
+        }
+    }
+    text
+}
+
+/// Lists a device's default configuration followed by everything it supports.
+fn draw_configs(
+    frame: &mut Frame,
+    title: &str,
+    configs: Option<(
+        &Option<cpal::SupportedStreamConfig>,
+        &Vec<cpal::SupportedStreamConfigRange>,
+    )>,
+    area: Rect,
+    theme: &Theme,
+) {
+    let lines: Vec<Line> = match configs {
+        Some((default, ranges)) => default
+            .iter()
+            .map(|config| {
+                Line::styled(
+                    format!(" default: {}", devices::describe_config(config)),
+                    theme.accent,
+                )
+            })
+            .chain(
+                ranges
+                    .iter()
+                    .map(|range| Line::from(format!(" {}", devices::describe_range(range)))),
+            )
+            .collect(),
+        None => vec![Line::styled(" no device", theme.muted)],
+    };
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(Span::styled(title, theme.title))),
+        area,
+    );
+}
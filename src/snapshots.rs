@@ -0,0 +1,145 @@
+// In-memory snapshots of every effect parameter, to flip between two settings and compare them.
+//
+// Edits always go to the live parameters. Switching slots stores them in the slot being left and
+// loads the other one; the effects ramp to the new values, so the switch doesn't click.
+
+use crate::effect_params::{EffectParams, Param};
+use crate::history::Change;
+
+pub const SLOT_NAMES: [&str; 2] = ["A", "B"];
+
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    values: Vec<(Param, f32)>,
+}
+
+impl Snapshot {
+    pub fn capture(params: &EffectParams) -> Self {
+        Self {
+            values: Param::ALL
+                .iter()
+                .map(|&param| (param, params.get(param)))
+                .collect(),
+        }
+    }
+
+    /// The changes that take `params` to this snapshot.
+    pub fn changes_from(&self, params: &EffectParams) -> Vec<Change> {
+        self.values
+            .iter()
+            .map(|&(param, to)| Change {
+                param,
+                from: params.get(param),
+                to,
+            })
+            .filter(|change| change.from != change.to)
+            .collect()
+    }
+}
+
+pub struct Snapshots {
+    slots: Vec<Option<Snapshot>>,
+    active: usize,
+}
+
+impl Snapshots {
+    pub fn new() -> Self {
+        Self {
+            slots: vec![None; SLOT_NAMES.len()],
+            active: 0,
+        }
+    }
+
+    /// The slot the live parameters belong to.
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// Makes `slot` the active one without storing or loading anything, for undo and redo.
+    pub fn set_active(&mut self, slot: usize) {
+        self.active = slot;
+    }
+
+    /// The slot after the active one, wrapping around.
+    pub fn next(&self) -> usize {
+        (self.active + 1) % self.slots.len()
+    }
+
+    /// Whether `slot` holds anything yet.
+    pub fn is_stored(&self, slot: usize) -> bool {
+        self.slots[slot].is_some()
+    }
+
+    /// Stores the live parameters in the active slot and makes `slot` the active one. Returns the
+    /// changes that load it, for the caller to apply. An empty slot starts as a copy.
+    pub fn switch(&mut self, slot: usize, params: &EffectParams) -> Vec<Change> {
+        self.slots[self.active] = Some(Snapshot::capture(params));
+        self.active = slot;
+        match &self.slots[slot] {
+            Some(snapshot) => snapshot.changes_from(params),
+            None => Vec::new(),
+        }
+    }
+
+    /// Overwrites `slot` with the live parameters.
+    pub fn copy_to(&mut self, slot: usize, params: &EffectParams) {
+        self.slots[slot] = Some(Snapshot::capture(params));
+    }
+}
+
+impl Default for Snapshots {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect_params::Param;
+    use crate::history::{History, SlotChange};
+
+    /// Switches the way the app does, recording the switch for undo.
+    fn switch(snapshots: &mut Snapshots, history: &mut History, params: &EffectParams) {
+        let slot = SlotChange {
+            from: snapshots.active(),
+            to: snapshots.next(),
+        };
+        let changes = snapshots.switch(slot.to, params);
+        for change in &changes {
+            params.set(change.param, change.to);
+        }
+        history.record_switch(changes, slot);
+    }
+
+    fn undo(snapshots: &mut Snapshots, history: &mut History, params: &EffectParams) {
+        if let Some(Some(slot)) = history.undo(params) {
+            snapshots.set_active(slot);
+        }
+    }
+
+    #[test]
+    fn undoing_a_switch_goes_back_to_the_slot_before() {
+        let params = EffectParams::new();
+        let mut snapshots = Snapshots::new();
+        let mut history = History::new();
+
+        params.set(Param::DelayDecay, 0.2);
+        switch(&mut snapshots, &mut history, &params);
+        params.set(Param::DelayDecay, 0.9);
+        switch(&mut snapshots, &mut history, &params);
+        assert_eq!(snapshots.active(), 0);
+        assert_eq!(params.get(Param::DelayDecay), 0.2);
+
+        undo(&mut snapshots, &mut history, &params);
+        assert_eq!(snapshots.active(), 1);
+        assert_eq!(params.get(Param::DelayDecay), 0.9);
+
+        // B's settings go back into B, and A still has its own.
+        switch(&mut snapshots, &mut history, &params);
+        assert_eq!(snapshots.active(), 0);
+        assert_eq!(params.get(Param::DelayDecay), 0.2);
+        switch(&mut snapshots, &mut history, &params);
+        assert_eq!(params.get(Param::DelayDecay), 0.9);
+    }
+}
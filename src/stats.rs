@@ -0,0 +1,192 @@
+// Glitch counters, ring buffer fill and callback timing, gathered in the stream callbacks and
+// shown in the status bar.
+//
+// Everything is a plain atomic so the callbacks never wait on the UI. Fill levels and callback
+// times are collected over a window that the pipeline thread rolls over once a second; the UI
+// shows the last complete window.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+#[derive(Default)]
+pub struct StreamStats {
+    underruns: AtomicU64,
+    overruns: AtomicU64,
+    fill_min: AtomicUsize,
+    fill_max: AtomicUsize,
+    callback_max_us: AtomicU64,
+    last_fill_min: AtomicUsize,
+    last_fill_max: AtomicUsize,
+    last_callback_max_us: AtomicU64,
+    /// Length of the last processing callback, in microseconds of audio.
+    period_us: AtomicU64,
+    /// Largest output callback seen, in samples.
+    period_samples: AtomicUsize,
+    capacity: AtomicUsize,
+    target_fill: AtomicUsize,
+    channels: AtomicUsize,
+    samples_per_second: AtomicUsize,
+    adaptive: AtomicBool,
+}
+
+/// A copy of the statistics, with buffer levels converted to milliseconds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StatsSnapshot {
+    pub underruns: u64,
+    pub overruns: u64,
+    pub fill_min_ms: f32,
+    pub fill_max_ms: f32,
+    pub target_ms: f32,
+    pub capacity_ms: f32,
+    pub callback_ms: f32,
+    pub period_ms: f32,
+    /// Length of the largest output callback.
+    pub output_period_ms: f32,
+    /// Frames in the largest output callback.
+    pub output_period_frames: usize,
+    pub adaptive: bool,
+}
+
+impl StatsSnapshot {
+    /// Roughly how long sound takes through the app: a period waiting to be processed, the
+    /// ring buffer and a period waiting to be played.
+    pub fn latency_ms(&self) -> f32 {
+        self.period_ms + self.target_ms + self.output_period_ms
+    }
+
+    /// The share of the time between callbacks the slowest one spent working, as a percentage.
+    pub fn cpu_load(&self) -> f32 {
+        if self.period_ms > 0.0 {
+            self.callback_ms / self.period_ms * 100.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// What a window of callbacks looked like, for the latency controller.
+#[derive(Clone, Copy, Debug)]
+pub struct Window {
+    pub underruns: u64,
+    /// Lowest fill level seen, or `None` if the output callback didn't run.
+    pub fill_min: Option<usize>,
+    pub period_samples: usize,
+}
+
+impl StreamStats {
+    pub fn new() -> Self {
+        let stats = Self::default();
+        stats.fill_min.store(usize::MAX, Ordering::Relaxed);
+        stats
+    }
+
+    /// Resets everything for freshly started streams. `capacity` and `target_fill` are ring
+    /// buffer sizes in samples of `channels` interleaved channels at `sample_rate`.
+    pub fn start(
+        &self,
+        capacity: usize,
+        target_fill: usize,
+        channels: usize,
+        sample_rate: u32,
+        adaptive: bool,
+    ) {
+        self.underruns.store(0, Ordering::Relaxed);
+        self.overruns.store(0, Ordering::Relaxed);
+        self.fill_min.store(usize::MAX, Ordering::Relaxed);
+        self.fill_max.store(0, Ordering::Relaxed);
+        self.callback_max_us.store(0, Ordering::Relaxed);
+        self.last_fill_min.store(0, Ordering::Relaxed);
+        self.last_fill_max.store(0, Ordering::Relaxed);
+        self.last_callback_max_us.store(0, Ordering::Relaxed);
+        self.period_us.store(0, Ordering::Relaxed);
+        self.period_samples.store(0, Ordering::Relaxed);
+        self.capacity.store(capacity, Ordering::Relaxed);
+        self.target_fill.store(target_fill, Ordering::Relaxed);
+        self.channels.store(channels.max(1), Ordering::Relaxed);
+        self.samples_per_second
+            .store(sample_rate as usize * channels, Ordering::Relaxed);
+        self.adaptive.store(adaptive, Ordering::Relaxed);
+    }
+
+    /// The output had nothing to play.
+    pub fn record_underrun(&self) {
+        self.underruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The input had nowhere to put its samples.
+    pub fn record_overrun(&self) {
+        self.overruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Called by the output callback with the ring buffer fill level and its own size, both in
+    /// samples.
+    pub fn record_fill(&self, fill: usize, period: usize) {
+        self.fill_min.fetch_min(fill, Ordering::Relaxed);
+        self.fill_max.fetch_max(fill, Ordering::Relaxed);
+        self.period_samples.fetch_max(period, Ordering::Relaxed);
+    }
+
+    /// Called by the callback that runs the effects with how long it took and how much audio it
+    /// handled.
+    pub fn record_callback(&self, elapsed: Duration, period: Duration) {
+        self.callback_max_us
+            .fetch_max(elapsed.as_micros() as u64, Ordering::Relaxed);
+        self.period_us
+            .store(period.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// The fill level, in samples, the output callback should hold the ring buffer at.
+    pub fn target_fill(&self) -> usize {
+        self.target_fill.load(Ordering::Relaxed)
+    }
+
+    /// Moves the target fill level, rounded down to whole frames.
+    pub fn set_target_fill(&self, target_fill: usize) {
+        let channels = self.channels.load(Ordering::Relaxed);
+        self.target_fill
+            .store(target_fill / channels * channels, Ordering::Relaxed);
+    }
+
+    /// Publishes the current window for the UI and starts a new one.
+    pub fn roll_window(&self) -> Window {
+        let fill_min = self.fill_min.swap(usize::MAX, Ordering::Relaxed);
+        let fill_max = self.fill_max.swap(0, Ordering::Relaxed);
+        let callback_max_us = self.callback_max_us.swap(0, Ordering::Relaxed);
+        let fill_min = (fill_min != usize::MAX).then_some(fill_min);
+        self.last_fill_min
+            .store(fill_min.unwrap_or(0), Ordering::Relaxed);
+        self.last_fill_max.store(fill_max, Ordering::Relaxed);
+        self.last_callback_max_us
+            .store(callback_max_us, Ordering::Relaxed);
+        Window {
+            underruns: self.underruns.load(Ordering::Relaxed),
+            fill_min,
+            period_samples: self.period_samples.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let samples_per_ms = self.samples_per_second.load(Ordering::Relaxed) as f32 / 1_000.0;
+        let ms = |samples: &AtomicUsize| {
+            if samples_per_ms > 0.0 {
+                samples.load(Ordering::Relaxed) as f32 / samples_per_ms
+            } else {
+                0.0
+            }
+        };
+        StatsSnapshot {
+            underruns: self.underruns.load(Ordering::Relaxed),
+            overruns: self.overruns.load(Ordering::Relaxed),
+            fill_min_ms: ms(&self.last_fill_min),
+            fill_max_ms: ms(&self.last_fill_max),
+            target_ms: ms(&self.target_fill),
+            capacity_ms: ms(&self.capacity),
+            callback_ms: self.last_callback_max_us.load(Ordering::Relaxed) as f32 / 1_000.0,
+            period_ms: self.period_us.load(Ordering::Relaxed) as f32 / 1_000.0,
+            output_period_ms: ms(&self.period_samples),
+            output_period_frames: self.period_samples.load(Ordering::Relaxed)
+                / self.channels.load(Ordering::Relaxed).max(1),
+            adaptive: self.adaptive.load(Ordering::Relaxed),
+        }
+    }
+}
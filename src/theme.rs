@@ -0,0 +1,256 @@
+// Colours and text styles the UI is drawn with.
+//
+// A theme gives a style to each kind of thing on screen. There are a few built-in themes, and the
+// config file can define more in `[themes.<name>]` tables, each starting from a built-in one:
+//
+// [themes.mine]
+// base = "high-contrast"
+// title = "bold cyan"
+// selected = "black on yellow"
+//
+// A style is a list of words: colour names like `red`, `lightblue` or `#ff8800`, `on` and a
+// colour for the background, and `bold`, `dim`, `italic`, `underlined` or `reversed`.
+
+use ratatui::style::{Color, Modifier, Style};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug)]
+pub struct Theme {
+    /// Tab titles.
+    pub tab: Style,
+    pub active_tab: Style,
+    /// Panel and overlay titles.
+    pub title: Style,
+    /// The lines of knobs and faders.
+    pub control: Style,
+    /// The selected control, setting or command.
+    pub selected: Style,
+    /// Things to pick out: key names, a value being typed in, the active snapshot.
+    pub accent: Style,
+    pub good: Style,
+    pub warning: Style,
+    pub error: Style,
+    /// Things in the background: empty slots, debug lines, hints.
+    pub muted: Style,
+    /// A switch that is on.
+    pub switch_on: Style,
+}
+
+/// Names of the built-in themes.
+pub const BUILT_IN: &[&str] = &["default", "high-contrast", "monochrome"];
+
+impl Theme {
+    pub fn built_in(name: &str) -> Option<Self> {
+        let theme = match name {
+            "default" => Self {
+                tab: Style::default().fg(Color::Green),
+                active_tab: Style::default().fg(Color::Yellow),
+                title: Style::default()
+                    .fg(Color::Magenta)
+                    .add_modifier(Modifier::BOLD),
+                control: Style::default().fg(Color::White),
+                selected: Style::default().add_modifier(Modifier::REVERSED),
+                accent: Style::default().fg(Color::Yellow),
+                good: Style::default().fg(Color::Green),
+                warning: Style::default().fg(Color::Yellow),
+                error: Style::default().fg(Color::Red),
+                muted: Style::default().fg(Color::DarkGray),
+                switch_on: Style::default().fg(Color::Black).bg(Color::Green),
+            },
+            "high-contrast" => Self {
+                tab: Style::default().fg(Color::White),
+                active_tab: Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+                title: Style::default()
+                    .fg(Color::White)
+                    .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                control: Style::default().fg(Color::White),
+                selected: Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+                accent: Style::default()
+                    .fg(Color::LightYellow)
+                    .add_modifier(Modifier::BOLD),
+                good: Style::default()
+                    .fg(Color::LightGreen)
+                    .add_modifier(Modifier::BOLD),
+                warning: Style::default()
+                    .fg(Color::LightYellow)
+                    .add_modifier(Modifier::BOLD),
+                error: Style::default()
+                    .fg(Color::White)
+                    .bg(Color::Red)
+                    .add_modifier(Modifier::BOLD),
+                muted: Style::default().fg(Color::Gray),
+                switch_on: Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::LightGreen)
+                    .add_modifier(Modifier::BOLD),
+            },
+            // Only the terminal's own colours, told apart by weight and reversing.
+            "monochrome" => Self {
+                tab: Style::default(),
+                active_tab: Style::default().add_modifier(Modifier::REVERSED),
+                title: Style::default().add_modifier(Modifier::BOLD),
+                control: Style::default(),
+                selected: Style::default().add_modifier(Modifier::REVERSED),
+                accent: Style::default().add_modifier(Modifier::BOLD),
+                good: Style::default(),
+                warning: Style::default().add_modifier(Modifier::BOLD),
+                error: Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                muted: Style::default().add_modifier(Modifier::DIM),
+                switch_on: Style::default().add_modifier(Modifier::REVERSED),
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    /// The theme called `name`: one of `user` from the config file, or a built-in one.
+    pub fn load(
+        name: &str,
+        user: &HashMap<String, HashMap<String, String>>,
+    ) -> anyhow::Result<Self> {
+        if let Some(styles) = user.get(name) {
+            let base = styles.get("base").map_or("default", String::as_str);
+            let mut theme = Self::built_in(base).ok_or_else(|| {
+                anyhow::anyhow!("theme {name:?} is based on unknown theme {base:?}")
+            })?;
+            for (slot, text) in styles.iter().filter(|(slot, _)| *slot != "base") {
+                let style = theme
+                    .slot_mut(slot)
+                    .ok_or_else(|| anyhow::anyhow!("unknown style {slot:?} in theme {name:?}"))?;
+                *style = parse_style(text)
+                    .map_err(|err| anyhow::anyhow!("{slot} in theme {name:?}: {err}"))?;
+            }
+            return Ok(theme);
+        }
+        Self::built_in(name).ok_or_else(|| {
+            let mut names: Vec<&str> = BUILT_IN.to_vec();
+            names.extend(user.keys().map(String::as_str));
+            anyhow::anyhow!("unknown theme {name:?}, pick one of {}", names.join(", "))
+        })
+    }
+
+    /// The colour to draw knob lines in, which can't take modifiers.
+    pub fn control_color(&self) -> Color {
+        self.control.fg.unwrap_or(Color::Reset)
+    }
+
+    fn slot_mut(&mut self, slot: &str) -> Option<&mut Style> {
+        let style = match slot {
+            "tab" => &mut self.tab,
+            "active_tab" => &mut self.active_tab,
+            "title" => &mut self.title,
+            "control" => &mut self.control,
+            "selected" => &mut self.selected,
+            "accent" => &mut self.accent,
+            "good" => &mut self.good,
+            "warning" => &mut self.warning,
+            "error" => &mut self.error,
+            "muted" => &mut self.muted,
+            "switch_on" => &mut self.switch_on,
+            _ => return None,
+        };
+        Some(style)
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::built_in("default").expect("the default theme is built in")
+    }
+}
+
+/// Reads a style like `bold black on yellow`.
+fn parse_style(text: &str) -> anyhow::Result<Style> {
+    let mut style = Style::default();
+    let mut words = text.split_whitespace();
+    while let Some(word) = words.next() {
+        style = match word.to_ascii_lowercase().as_str() {
+            "bold" => style.add_modifier(Modifier::BOLD),
+            "dim" => style.add_modifier(Modifier::DIM),
+            "italic" => style.add_modifier(Modifier::ITALIC),
+            "underlined" => style.add_modifier(Modifier::UNDERLINED),
+            "reversed" => style.add_modifier(Modifier::REVERSED),
+            "on" => {
+                let color = words
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("no colour after \"on\""))?;
+                style.bg(parse_color(color)?)
+            }
+            _ => style.fg(parse_color(word)?),
+        };
+    }
+    Ok(style)
+}
+
+fn parse_color(text: &str) -> anyhow::Result<Color> {
+    text.parse()
+        .map_err(|_| anyhow::anyhow!("unknown colour {text:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_theme(styles: &[(&str, &str)]) -> HashMap<String, HashMap<String, String>> {
+        let styles = styles
+            .iter()
+            .map(|(slot, text)| (slot.to_string(), text.to_string()))
+            .collect();
+        HashMap::from([("mine".to_string(), styles)])
+    }
+
+    #[test]
+    fn reads_styles() {
+        assert_eq!(
+            parse_style("bold black on yellow").unwrap(),
+            Theme::built_in("high-contrast").unwrap().selected
+        );
+        assert_eq!(
+            parse_style("Dim LightBlue").unwrap(),
+            Style::default()
+                .fg(Color::LightBlue)
+                .add_modifier(Modifier::DIM)
+        );
+        assert_eq!(
+            parse_style("#ff8800 on black").unwrap(),
+            Style::default()
+                .fg(Color::Rgb(0xff, 0x88, 0x00))
+                .bg(Color::Black)
+        );
+        assert_eq!(parse_style("").unwrap(), Style::default());
+    }
+
+    #[test]
+    fn rejects_bad_styles() {
+        assert!(parse_style("blurple").is_err());
+        assert!(parse_style("red on").is_err());
+        assert!(parse_style("on bold").is_err());
+    }
+
+    #[test]
+    fn user_themes_start_from_their_base() {
+        let user = user_theme(&[("base", "monochrome"), ("title", "cyan")]);
+        let theme = Theme::load("mine", &user).unwrap();
+        let base = Theme::built_in("monochrome").unwrap();
+        assert_eq!(theme.title, Style::default().fg(Color::Cyan));
+        assert_eq!(theme.selected, base.selected);
+
+        let theme = Theme::load("mine", &user_theme(&[("error", "red")])).unwrap();
+        assert_eq!(theme.selected, Theme::default().selected);
+        assert!(Theme::load("high-contrast", &user).is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_themes() {
+        assert!(Theme::load("nope", &HashMap::new()).is_err());
+        assert!(Theme::load("mine", &user_theme(&[("base", "nope")])).is_err());
+        assert!(Theme::load("mine", &user_theme(&[("titel", "red")])).is_err());
+        assert!(Theme::load("mine", &user_theme(&[("title", "blurple")])).is_err());
+    }
+}
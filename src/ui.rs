@@ -1,34 +1,158 @@
+use crate::app::{LOG_TAB, SETTINGS_TAB};
+use crate::engine_state::StreamStatus;
+use crate::log_ui::draw_log;
+use crate::overlay_ui::draw_overlay;
+use crate::settings_ui::draw_settings;
+use crate::snapshots::SLOT_NAMES;
+use crate::{app::App, effect_ui::delay_ui::draw_delay, effect_ui::distortion_ui::draw_distortion};
 use ratatui::{
-    layout::{Constraint, Layout, Rect},
-    style::{Color, Modifier, Style},
-    symbols,
-    text::{self, Span},
-    widgets::{
-        canvas::{self, Canvas, Circle, Map, MapResolution, Rectangle},
-        Axis, BarChart, Block, Cell, Chart, Dataset, Gauge, LineGauge, List, ListItem, Paragraph,
-        Row, Sparkline, Table, Tabs, Wrap,
-    },
     Frame,
+    layout::{Constraint, Layout, Margin, Rect},
+    style::Style,
+    text::{self, Line, Span},
+    widgets::{Block, Tabs},
 };
-use crate::{app::App, effect_ui::distortion_ui::draw_distortion, effect_ui::delay_ui::draw_delay};
+
+/// The CPU load from which it is shown in red, as a percentage.
+const CPU_WARNING: f32 = 75.0;
+/// Longest device name shown in the status bar, in characters.
+const MAX_DEVICE_NAME: usize = 24;
 
 pub fn draw(frame: &mut Frame, app: &mut App) {
-    let chunks = Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).split(frame.area());
+    let chunks = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(0),
+        Constraint::Length(2),
+    ])
+    .split(frame.area());
+    app.hit_areas.clear();
+    app.hit_areas.tabs = tab_areas(&app.tabs.titles, chunks[0]);
     let tabs = app
         .tabs
         .titles
         .iter()
-        .map(|t| text::Line::from(Span::styled(*t, Style::default().fg(Color::Green))))
+        .map(|t| text::Line::from(Span::styled(*t, app.theme.tab)))
         .collect::<Tabs>()
         .block(Block::bordered().title("Audio Oxidiser"))
-        .highlight_style(Style::default().fg(Color::Yellow))
+        .highlight_style(app.theme.active_tab)
         .select(app.tabs.index);
     frame.render_widget(tabs, chunks[0]);
     match app.tabs.index {
         0 => draw_distortion(frame, app, chunks[1]),
         1 => draw_delay(frame, app, chunks[1]),
         // 2 => draw_third_tab(frame, app, chunks[1]),
+        SETTINGS_TAB => draw_settings(frame, app, chunks[1]),
+        LOG_TAB => draw_log(frame, app, chunks[1]),
         _ => {}
     };
+    draw_status(frame, app, chunks[2]);
+    draw_overlay(frame, app, frame.area());
+}
+
+/// Where `Tabs` puts each title inside a bordered block: a space either side and a one column
+/// divider in between.
+fn tab_areas(titles: &[&str], area: Rect) -> Vec<Rect> {
+    let inner = area.inner(Margin::new(1, 1));
+    let mut x = inner.x;
+    titles
+        .iter()
+        .map(|title| {
+            let width = Line::from(*title).width() as u16 + 2;
+            let tab = Rect::new(x, inner.y, width, 1).intersection(inner);
+            x = x.saturating_add(width + 1);
+            tab
+        })
+        .collect()
 }
 
+/// Two lines: what the engine runs on and how it is doing, then buffer details, snapshots and
+/// messages.
+fn draw_status(frame: &mut Frame, app: &App, area: Rect) {
+    let rows = Layout::vertical([Constraint::Length(1), Constraint::Length(1)]).split(area);
+    let theme = &app.theme;
+    let (label, style) = match app.engine.status() {
+        StreamStatus::Starting => ("starting", theme.muted),
+        StreamStatus::Running => ("running", theme.good),
+        StreamStatus::Fallback => ("fallback", theme.warning),
+        StreamStatus::Reconnecting => ("reconnecting", theme.error),
+    };
+    let mut spans = vec![Span::styled(format!(" ● {label}"), style)];
+
+    let stats = app.engine.stats.snapshot();
+    if let Some(active) = app.engine.active() {
+        let buffer = active
+            .buffer_size
+            .unwrap_or(stats.output_period_frames as u32);
+        spans.push(Span::raw(format!(
+            " | {} | {} → {} | {} Hz | {buffer} frames",
+            active.host,
+            shorten(&active.input_device),
+            shorten(&active.output_device),
+            active.sample_rate,
+        )));
+    }
+    spans.push(Span::raw(format!(
+        " | latency {:.1} ms",
+        stats.latency_ms()
+    )));
+    let cpu_load = stats.cpu_load();
+    let cpu_style = if cpu_load >= CPU_WARNING {
+        theme.error
+    } else {
+        Style::default()
+    };
+    spans.push(Span::raw(" | CPU "));
+    spans.push(Span::styled(format!("{cpu_load:.0}%"), cpu_style));
+    let xrun_style = if stats.underruns + stats.overruns > 0 {
+        theme.error
+    } else {
+        Style::default()
+    };
+    spans.push(Span::raw(" | xruns "));
+    spans.push(Span::styled(
+        format!("{}↓ {}↑", stats.underruns, stats.overruns),
+        xrun_style,
+    ));
+    frame.render_widget(text::Line::from(spans), rows[0]);
+
+    let mut spans = Vec::new();
+    let margin = if stats.target_ms > 0.0 {
+        let mode = if stats.adaptive { "auto" } else { "fixed" };
+        format!(", target {:.0} ms {mode}", stats.target_ms)
+    } else {
+        String::new()
+    };
+    spans.push(Span::raw(format!(
+        " fill {:.0}-{:.0} of {:.0} ms{margin} | callback {:.2}/{:.2} ms",
+        stats.fill_min_ms, stats.fill_max_ms, stats.capacity_ms, stats.callback_ms, stats.period_ms
+    )));
+    spans.push(Span::raw(" |"));
+    for (slot, name) in SLOT_NAMES.iter().enumerate() {
+        let style = if slot == app.snapshots.active() {
+            theme.accent
+        } else if app.snapshots.is_stored(slot) {
+            Style::default()
+        } else {
+            theme.muted
+        };
+        spans.push(Span::styled(format!(" {name}"), style));
+    }
+    if let Some(error) = app.entry.as_ref().and_then(|entry| entry.error.as_ref()) {
+        spans.push(Span::styled(format!(" | {error}"), theme.error));
+    }
+    let message = app.engine.message();
+    if !message.is_empty() {
+        spans.push(Span::raw(format!(" | {message}")));
+    }
+    frame.render_widget(text::Line::from(spans), rows[1]);
+}
+
+/// Device names can be long; the status bar shows the start of them.
+fn shorten(name: &str) -> String {
+    if name.chars().count() > MAX_DEVICE_NAME {
+        let start: String = name.chars().take(MAX_DEVICE_NAME - 1).collect();
+        format!("{start}…")
+    } else {
+        name.to_string()
+    }
+}
@@ -0,0 +1,251 @@
+// How parameter values are shown and typed in.
+//
+// Values are stored the way the effects use them: gains as linear factors, times in seconds and
+// amounts as fractions. On screen they get the unit people think in, and typed values may use
+// any unit that converts to the parameter's, e.g. `350ms`, `0.5s` or `2Hz` for a time.
+//
+// Times can also be note lengths at the tempo from the config file: `1/8` is an eighth note,
+// `1/8.` or `1/8d` a dotted one and `1/8t` a triplet. A time that is one of those lengths is
+// shown with it, e.g. `250 ms (1/8)` at 120 BPM.
+
+/// The quietest gain that isn't silence, as far as stepping is concerned.
+const SILENCE_DB: f32 = -60.0;
+
+/// The tempo note lengths are timed against unless the config file sets one, in beats per
+/// minute.
+pub const DEFAULT_TEMPO: f32 = 120.0;
+/// The note lengths a time is shown as, in fractions of a whole note.
+const DIVISIONS: [u32; 6] = [1, 2, 4, 8, 16, 32];
+/// How close a time has to be to a note length to be shown as it.
+const DIVISION_TOLERANCE: f32 = 0.001;
+
+/// What a parameter's value means.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unit {
+    /// A linear gain factor, shown in decibels.
+    Gain,
+    /// A fraction from 0 to 1, shown as a percentage.
+    Percent,
+    /// A time in seconds, shown in milliseconds below a second.
+    Seconds,
+    /// On when 1, off when 0.
+    Switch,
+    /// The index of one of a few named options.
+    Choice(&'static [&'static str]),
+}
+
+impl Unit {
+    /// Shows `value` in the unit, with times that are a note length at `tempo` labelled as one.
+    pub fn format(self, value: f32, tempo: f32) -> String {
+        let text = match self {
+            Unit::Gain if value <= 0.0 => "-inf dB".to_string(),
+            Unit::Gain => format!("{:.1} dB", 20.0 * value.log10()),
+            Unit::Percent => format!("{:.1}%", value * 100.0),
+            Unit::Seconds if value < 1.0 => format!("{:.0} ms", value * 1000.0),
+            Unit::Seconds => format!("{value:.2} s"),
+            Unit::Switch if value >= 0.5 => "on".to_string(),
+            Unit::Switch => "off".to_string(),
+            Unit::Choice(options) => options
+                .get(value.round().max(0.0) as usize)
+                .unwrap_or(&"?")
+                .to_string(),
+        };
+        if self == Unit::Seconds
+            && let Some(division) = division_of(value, tempo)
+        {
+            return format!("{text} ({division})");
+        }
+        text
+    }
+
+    /// Moves `value` by `amount` of the unit it is shown in: decibels, percent, milliseconds or
+    /// options.
+    /// Gains below -60 dB count as silence, so stepping down ends at -inf and stepping up from
+    /// there starts at -60 dB.
+    pub fn offset(self, value: f32, amount: f32) -> f32 {
+        match self {
+            Unit::Gain => {
+                let db = if value > 0.0 {
+                    20.0 * value.log10()
+                } else {
+                    SILENCE_DB
+                };
+                let db = db + amount;
+                if db < SILENCE_DB || (value <= 0.0 && amount < 0.0) {
+                    0.0
+                } else {
+                    10.0_f32.powf(db / 20.0)
+                }
+            }
+            Unit::Percent => value + amount / 100.0,
+            Unit::Seconds => value + amount / 1000.0,
+            Unit::Switch if amount > 0.0 => 1.0,
+            Unit::Switch if amount < 0.0 => 0.0,
+            Unit::Switch => value,
+            Unit::Choice(_) => value.round() + amount,
+        }
+    }
+
+    /// Reads a typed value. A number without a unit is taken to be in the unit values are shown
+    /// in, and a note length is timed at `tempo`.
+    pub fn parse(self, text: &str, tempo: f32) -> Result<f32, String> {
+        let text = text.trim();
+        if self == Unit::Seconds && text.contains('/') {
+            return parse_division(text, tempo);
+        }
+        if self == Unit::Switch {
+            return match text.to_ascii_lowercase().as_str() {
+                "on" | "1" | "true" => Ok(1.0),
+                "off" | "0" | "false" => Ok(0.0),
+                _ => Err(format!("{text:?} is not on or off")),
+            };
+        }
+        if let Unit::Choice(options) = self {
+            return options
+                .iter()
+                .position(|option| option.eq_ignore_ascii_case(text))
+                .map(|index| index as f32)
+                .ok_or_else(|| format!("{text:?} is not one of {}", options.join(", ")));
+        }
+        if self == Unit::Gain && text.to_ascii_lowercase().trim_end_matches("db").trim() == "-inf" {
+            return Ok(0.0);
+        }
+
+        let split = text
+            .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+')))
+            .unwrap_or(text.len());
+        let (number, suffix) = text.split_at(split);
+        let number: f32 = number
+            .parse()
+            .map_err(|_| format!("{text:?} doesn't start with a number"))?;
+        let suffix = suffix.trim().to_ascii_lowercase();
+
+        let value = match (self, suffix.as_str()) {
+            (Unit::Gain, "" | "db") => 10.0_f32.powf(number / 20.0),
+            (Unit::Gain, "x") => number,
+            (Unit::Gain | Unit::Percent, "%") => number / 100.0,
+            (Unit::Percent, "") => number / 100.0,
+            (Unit::Seconds, "" | "ms") => number / 1000.0,
+            (Unit::Seconds, "s") => number,
+            (Unit::Seconds, "hz") if number > 0.0 => 1.0 / number,
+            _ => return Err(format!("can't use {suffix:?} here")),
+        };
+        if value.is_finite() {
+            Ok(value)
+        } else {
+            Err(format!("{text:?} is out of range"))
+        }
+    }
+}
+
+/// The length of a whole note at `tempo`, in seconds.
+fn whole_note(tempo: f32) -> f32 {
+    4.0 * 60.0 / tempo
+}
+
+/// Reads a note length like `1/8`, `3/16`, `1/8.` or `1/8t` as seconds at `tempo`.
+fn parse_division(text: &str, tempo: f32) -> Result<f32, String> {
+    let lower = text.to_ascii_lowercase();
+    let (fraction, factor) = if let Some(fraction) = lower.strip_suffix('t') {
+        (fraction, 2.0 / 3.0)
+    } else if let Some(fraction) = lower.strip_suffix(['.', 'd']) {
+        (fraction, 1.5)
+    } else {
+        (lower.as_str(), 1.0)
+    };
+    let parts = fraction
+        .split_once('/')
+        .and_then(|(beats, division)| {
+            Some((
+                beats.trim().parse::<u32>().ok()?,
+                division.trim().parse::<u32>().ok()?,
+            ))
+        })
+        .filter(|&(beats, division)| beats > 0 && division > 0);
+    let Some((beats, division)) = parts else {
+        return Err(format!("{text:?} is not a note length like 1/8"));
+    };
+    Ok(whole_note(tempo) * beats as f32 / division as f32 * factor)
+}
+
+/// The note length `seconds` is at `tempo`, if it is one: plain, dotted or triplet.
+fn division_of(seconds: f32, tempo: f32) -> Option<String> {
+    let whole = whole_note(tempo);
+    [(1.0, ""), (1.5, "."), (2.0 / 3.0, "t")]
+        .into_iter()
+        .flat_map(|(factor, suffix)| {
+            DIVISIONS.map(move |division| (whole / division as f32 * factor, division, suffix))
+        })
+        .find(|(length, _, _)| (length - seconds).abs() < DIVISION_TOLERANCE)
+        .map(|(_, division, suffix)| format!("1/{division}{suffix}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    fn parse(unit: Unit, text: &str) -> Result<f32, String> {
+        unit.parse(text, DEFAULT_TEMPO)
+    }
+
+    #[test]
+    fn parses_values_with_units() {
+        assert!(close(parse(Unit::Gain, "-6dB").unwrap(), 0.501_187));
+        assert!(close(parse(Unit::Gain, "50%").unwrap(), 0.5));
+        assert_eq!(parse(Unit::Gain, "-inf dB"), Ok(0.0));
+        assert!(close(parse(Unit::Seconds, "350ms").unwrap(), 0.35));
+        assert!(close(parse(Unit::Seconds, "0.5 s").unwrap(), 0.5));
+        assert!(close(parse(Unit::Seconds, "2Hz").unwrap(), 0.5));
+        assert!(close(parse(Unit::Percent, "40%").unwrap(), 0.4));
+        assert_eq!(parse(Unit::Switch, "On"), Ok(1.0));
+    }
+
+    #[test]
+    fn times_can_be_note_lengths() {
+        assert!(close(parse(Unit::Seconds, "1/8").unwrap(), 0.25));
+        assert!(close(parse(Unit::Seconds, "1/8.").unwrap(), 0.375));
+        assert!(close(parse(Unit::Seconds, "1/8d").unwrap(), 0.375));
+        assert!(close(parse(Unit::Seconds, "1/4t").unwrap(), 1.0 / 3.0));
+        assert!(close(parse(Unit::Seconds, "3/16").unwrap(), 0.375));
+        assert!(close(Unit::Seconds.parse("1/4", 90.0).unwrap(), 2.0 / 3.0));
+        assert!(parse(Unit::Seconds, "1/0").is_err());
+        assert!(parse(Unit::Seconds, "1/x").is_err());
+        assert!(parse(Unit::Gain, "1/8").is_err());
+
+        assert_eq!(Unit::Seconds.format(0.25, 120.0), "250 ms (1/8)");
+        assert_eq!(Unit::Seconds.format(0.375, 120.0), "375 ms (1/8.)");
+        assert_eq!(Unit::Seconds.format(1.0, 120.0), "1.00 s (1/2)");
+        assert_eq!(Unit::Seconds.format(0.3, 120.0), "300 ms");
+        assert_eq!(Unit::Seconds.format(0.25, 100.0), "250 ms");
+    }
+
+    #[test]
+    fn choices_are_named() {
+        let unit = Unit::Choice(&["off", "low-pass", "high-pass"]);
+        assert_eq!(parse(unit, "Low-Pass"), Ok(1.0));
+        assert_eq!(unit.format(2.0, DEFAULT_TEMPO), "high-pass");
+        assert_eq!(unit.offset(1.0, -1.0), 0.0);
+        assert!(parse(unit, "band-pass").is_err());
+    }
+
+    #[test]
+    fn plain_numbers_are_in_the_unit_shown() {
+        assert!(close(parse(Unit::Gain, "-6").unwrap(), 0.501_187));
+        assert!(close(parse(Unit::Seconds, "350").unwrap(), 0.35));
+        assert!(close(parse(Unit::Percent, "40").unwrap(), 0.4));
+        assert_eq!(parse(Unit::Switch, "0"), Ok(0.0));
+    }
+
+    #[test]
+    fn rejects_what_doesnt_fit() {
+        assert!(parse(Unit::Seconds, "-6dB").is_err());
+        assert!(parse(Unit::Gain, "350ms").is_err());
+        assert!(parse(Unit::Seconds, "0Hz").is_err());
+        assert!(parse(Unit::Percent, "lots").is_err());
+        assert!(parse(Unit::Switch, "maybe").is_err());
+    }
+}
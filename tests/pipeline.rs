@@ -0,0 +1,213 @@
+// The whole pipeline, run headless on the null and file backends.
+
+use audio_oxidiser::backend::{
+    file::FileBackend,
+    null::{NullBackend, NullControl},
+};
+use audio_oxidiser::cli::Opt;
+use audio_oxidiser::effect_params::EffectParams;
+use audio_oxidiser::engine_state::{EngineState, StreamStatus};
+use audio_oxidiser::pipeline;
+use clap::Parser;
+use std::path::PathBuf;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+    mpsc,
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SAMPLE_RATE: u32 = 48_000;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("audio_oxidiser-{}-{name}", std::process::id()))
+}
+
+/// Runs the pipeline on the null backend for half a second, returning the engine state as it was
+/// then.
+fn run_null(args: &[&str]) -> Arc<EngineState> {
+    let opt = Opt::parse_from(["audio_oxidiser", "--backend", "null"].iter().chain(args));
+    let running = Arc::new(AtomicBool::new(true));
+    let engine = Arc::new(EngineState::new());
+    let (_commands, receiver) = mpsc::channel();
+
+    let pipeline = {
+        let running = Arc::clone(&running);
+        let engine = Arc::clone(&engine);
+        thread::spawn(move || {
+            pipeline::init_pipeline(
+                running,
+                Arc::new(EffectParams::new()),
+                engine,
+                receiver,
+                opt,
+                Box::new(NullBackend::new()),
+            )
+        })
+    };
+
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(engine.status(), StreamStatus::Running);
+    assert!(
+        engine.heartbeat.load(Ordering::Relaxed) > 0,
+        "the output callback never ran"
+    );
+    running.store(false, Ordering::SeqCst);
+    pipeline.join().unwrap().unwrap();
+    engine
+}
+
+#[test]
+fn null_backend_keeps_the_streams_running() {
+    run_null(&["--latency", "20"]);
+}
+
+#[test]
+fn duplex_plays_every_period() {
+    let engine = run_null(&["--duplex", "--buffer-size", "64"]);
+    let stats = engine.stats.snapshot();
+    assert_eq!(stats.overruns, 0);
+    assert_eq!(stats.underruns, 0);
+
+    // The hand-off is sized from the period, so it has to be set.
+    assert!(Opt::try_parse_from(["audio_oxidiser", "--duplex"]).is_err());
+}
+
+/// Waits up to ten seconds for `done`.
+fn wait_for(what: &str, done: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for {what}");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn failed_streams_are_rebuilt_and_fallbacks_undone() {
+    let opt = Opt::parse_from([
+        "audio_oxidiser",
+        "--backend",
+        "null",
+        "--input-device",
+        "alsa:missing",
+    ]);
+    let control = Arc::new(NullControl::new());
+    control.devices_present.store(false, Ordering::SeqCst);
+    let running = Arc::new(AtomicBool::new(true));
+    let engine = Arc::new(EngineState::new());
+    let (_commands, receiver) = mpsc::channel();
+
+    let pipeline = {
+        let running = Arc::clone(&running);
+        let engine = Arc::clone(&engine);
+        let backend = NullBackend::with_control(Arc::clone(&control));
+        thread::spawn(move || {
+            pipeline::init_pipeline(
+                running,
+                Arc::new(EffectParams::new()),
+                engine,
+                receiver,
+                opt,
+                Box::new(backend),
+            )
+        })
+    };
+
+    // The requested device is missing, so the streams start on a stand-in.
+    wait_for("the fallback", || engine.status() == StreamStatus::Fallback);
+    assert_eq!(control.starts.load(Ordering::SeqCst), 1);
+
+    // A failing stream is rebuilt, still on the stand-in.
+    control.fail.store(true, Ordering::SeqCst);
+    wait_for("the rebuild", || {
+        control.starts.load(Ordering::SeqCst) == 2 && engine.status() == StreamStatus::Fallback
+    });
+
+    // Once the device is back the streams move over to it.
+    control.devices_present.store(true, Ordering::SeqCst);
+    wait_for("the requested device", || {
+        engine.status() == StreamStatus::Running
+    });
+    assert_eq!(control.starts.load(Ordering::SeqCst), 3);
+
+    running.store(false, Ordering::SeqCst);
+    pipeline.join().unwrap().unwrap();
+}
+
+#[test]
+fn file_backend_records_the_processed_input() {
+    let input = temp_path("impulse.wav");
+    let output = temp_path("processed.wav");
+
+    // One second of silence with a single click at the start.
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(&input, spec).unwrap();
+    writer.write_sample(0.5_f32).unwrap();
+    for _ in 1..SAMPLE_RATE {
+        writer.write_sample(0.0_f32).unwrap();
+    }
+    writer.finalize().unwrap();
+
+    let latency_ms = 10;
+    let opt = Opt::parse_from([
+        "audio_oxidiser",
+        "--backend",
+        "file",
+        "--latency",
+        &latency_ms.to_string(),
+        "--fast",
+    ]);
+    let running = Arc::new(AtomicBool::new(true));
+    let (_commands, receiver) = mpsc::channel();
+    let backend = FileBackend::new(&input, &output, false).unwrap();
+
+    let pipeline = {
+        let running = Arc::clone(&running);
+        thread::spawn(move || {
+            pipeline::init_pipeline(
+                running,
+                Arc::new(EffectParams::new()),
+                Arc::new(EngineState::new()),
+                receiver,
+                opt,
+                Box::new(backend),
+            )
+        })
+    };
+
+    // The pipeline stops by itself once the file has been played.
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while running.load(Ordering::SeqCst) {
+        assert!(Instant::now() < deadline, "the file backend never finished");
+        thread::sleep(Duration::from_millis(10));
+    }
+    pipeline.join().unwrap().unwrap();
+
+    let reader = hound::WavReader::open(&output).unwrap();
+    assert_eq!(reader.spec().channels, 1);
+    assert_eq!(reader.spec().sample_rate, SAMPLE_RATE);
+    let samples: Vec<f32> = reader.into_samples().map(Result::unwrap).collect();
+    // The input plus the tail that lets the delay ring out.
+    assert!(samples.len() > SAMPLE_RATE as usize);
+
+    // The click comes out distorted, delayed by the latency margin.
+    let (peak_at, peak) = samples
+        .iter()
+        .copied()
+        .enumerate()
+        .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+        .unwrap();
+    let latency_frames = SAMPLE_RATE as usize * latency_ms / 1_000;
+    assert_eq!(peak_at, latency_frames);
+    // Half drive pushes it into the clipper 10.5 times louder.
+    assert!((peak - (0.5_f32 * 10.5).tanh()).abs() < 1e-3, "peak was {peak}");
+
+    std::fs::remove_file(&input).unwrap();
+    std::fs::remove_file(&output).unwrap();
+}